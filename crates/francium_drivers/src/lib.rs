@@ -36,6 +36,11 @@ pub trait Timer {
     fn reset_timer(&mut self);
     fn enable_timer(&mut self);
 
+    // One-shot deadline, in the same timebase as get_counter_ns.
+    // Timers that can only tick periodically just ignore these, and the timer queue gets checked every tick instead.
+    fn set_deadline_ns(&mut self, _deadline_ns: u64) {}
+    fn clear_deadline(&mut self) {}
+
     fn get_counter_ns(&self) -> u64;
 }
 
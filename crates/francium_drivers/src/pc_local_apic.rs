@@ -1,4 +1,5 @@
-use crate::InterruptController;
+use crate::pit_timer;
+use crate::{InterruptController, Timer};
 use tock_registers::interfaces::*;
 use tock_registers::register_structs;
 use tock_registers::registers::*;
@@ -77,7 +78,7 @@ register_structs! {
         (0x390 => timer_current_count: ReadOnly<u32>),
         (0x394 => _reserved26),
 
-        (0x3e0 => timer_divide_config: ReadOnly<u32>),
+        (0x3e0 => timer_divide_config: ReadWrite<u32>),
         (0x3e4 => _reserved27),
 
         // The end of the struct is marked as follows.
@@ -132,7 +133,7 @@ impl LocalApic {
     }
 }
 
-use francium_x86::msr;
+use francium_x86::{cpuid, msr};
 
 impl InterruptController for LocalApic {
     fn init(&mut self) {
@@ -157,3 +158,114 @@ impl InterruptController for LocalApic {
         0
     }
 }
+
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_MODE_ONE_SHOT: u32 = 0 << 17;
+const LVT_TIMER_MODE_TSC_DEADLINE: u32 = 2 << 17;
+
+const TIMER_DIVIDE_BY_1: u32 = 0b1011;
+
+// How long we spin on the PIT for, when calibrating.
+const CALIBRATION_PERIOD_US: u64 = 10000;
+
+pub struct LocalApicTimer {
+    regs: &'static mut LocalApicRegs,
+    vector: u8,
+    use_tsc_deadline: bool,
+    tsc_frequency: u64,
+    apic_timer_frequency: u64,
+}
+
+impl LocalApicTimer {
+    pub fn new(base_address_virt: usize, vector: u8) -> LocalApicTimer {
+        LocalApicTimer {
+            regs: unsafe { (base_address_virt as *mut LocalApicRegs).as_mut().unwrap() },
+            vector: vector,
+            use_tsc_deadline: false,
+            tsc_frequency: 0,
+            apic_timer_frequency: 0,
+        }
+    }
+
+    fn tsc_to_ns(&self, tsc: u64) -> u64 {
+        ((tsc as u128 * 1000000000) / self.tsc_frequency as u128) as u64
+    }
+
+    fn ns_to_tsc(&self, ns: u64) -> u64 {
+        ((ns as u128 * self.tsc_frequency as u128) / 1000000000) as u64
+    }
+}
+
+impl Timer for LocalApicTimer {
+    fn init(&mut self) {
+        self.use_tsc_deadline = cpuid::has_tsc_deadline();
+
+        // The TSC is our counter either way, so we always need its frequency.
+        let tsc_start = msr::read_tsc();
+        pit_timer::spin_wait_us(CALIBRATION_PERIOD_US);
+        let tsc_end = msr::read_tsc();
+        self.tsc_frequency = ((tsc_end - tsc_start) as u64 * 1000000) / CALIBRATION_PERIOD_US;
+
+        if self.use_tsc_deadline {
+            self.regs
+                .lvt_timer
+                .set(LVT_TIMER_MODE_TSC_DEADLINE | self.vector as u32);
+        } else {
+            // No TSC deadline mode, so calibrate the APIC timer itself and count down from there.
+            self.regs.timer_divide_config.set(TIMER_DIVIDE_BY_1);
+            self.regs
+                .lvt_timer
+                .set(LVT_MASKED | LVT_TIMER_MODE_ONE_SHOT | self.vector as u32);
+            self.regs.timer_initial_count.set(u32::MAX);
+            pit_timer::spin_wait_us(CALIBRATION_PERIOD_US);
+            let elapsed = u32::MAX - self.regs.timer_current_count.get();
+            self.regs.timer_initial_count.set(0);
+
+            self.apic_timer_frequency = (elapsed as u64 * 1000000) / CALIBRATION_PERIOD_US;
+            self.regs
+                .lvt_timer
+                .set(LVT_TIMER_MODE_ONE_SHOT | self.vector as u32);
+        }
+    }
+
+    fn tick(&mut self) {}
+
+    // The local APIC timer is only ever used in one-shot mode.
+    fn set_period_us(&mut self, _us: u64) {}
+    fn reset_timer(&mut self) {}
+    fn enable_timer(&mut self) {}
+
+    fn set_deadline_ns(&mut self, deadline_ns: u64) {
+        if self.use_tsc_deadline {
+            // Writing 0 disarms the timer, so a deadline at 0 has to be nudged forward.
+            let deadline = self.ns_to_tsc(deadline_ns).max(1);
+            unsafe {
+                msr::write_tsc_deadline(deadline as usize);
+            }
+        } else {
+            let delta_ns = deadline_ns.saturating_sub(self.get_counter_ns());
+            let count = (delta_ns as u128 * self.apic_timer_frequency as u128) / 1000000000;
+            // If the deadline is further out than we can count, we'll just fire early and get re-armed.
+            let count = count.clamp(1, u32::MAX as u128) as u32;
+            self.regs.timer_initial_count.set(count);
+        }
+    }
+
+    fn clear_deadline(&mut self) {
+        if self.use_tsc_deadline {
+            unsafe {
+                msr::write_tsc_deadline(0);
+            }
+        } else {
+            self.regs.timer_initial_count.set(0);
+        }
+    }
+
+    fn get_counter_ns(&self) -> u64 {
+        if self.tsc_frequency == 0 {
+            // Not calibrated yet.
+            return 0;
+        }
+        self.tsc_to_ns(msr::read_tsc() as u64)
+    }
+}
@@ -1,5 +1,6 @@
 use crate::Timer;
 use core::arch::asm;
+use francium_x86::io_port::{inb, outb};
 
 pub struct PIT {
     reload_value: u16,
@@ -27,7 +28,7 @@ impl PIT {
 //const PIT_ACCESS_HIGH: u8 = 2 << 4;
 const PIT_ACCESS_BOTH: u8 = 3 << 4;
 
-//const PIT_OP_MODE_0: u8 = 0 << 1;
+const PIT_OP_MODE_0: u8 = 0 << 1;
 //const PIT_OP_MODE_1: u8 = 1 << 1;
 //const PIT_OP_MODE_2: u8 = 2 << 1;
 const PIT_OP_MODE_3: u8 = 3 << 1;
@@ -54,6 +55,25 @@ fn write_data_reg_u16(channel: u8, value: u16) {
     write_data_reg(channel, ((value & 0xff00) >> 8) as u8);
 }
 
+/// Busy-waits for `us` microseconds on PIT channel 2, which is only used to calibrate other timers.
+pub fn spin_wait_us(us: u64) {
+    let count = ((us * 1193182) / 1000000) as u16;
+
+    // Gate channel 2 on, and keep the speaker off.
+    let port_b = inb(0x61);
+    outb(0x61, (port_b & !0x02) | 0x01);
+
+    // In mode 0, the output goes high once the count hits zero.
+    write_mode_command_reg(2, PIT_ACCESS_BOTH | PIT_OP_MODE_0 | PIT_BINARY_MODE);
+    write_data_reg_u16(2, count);
+
+    while inb(0x61) & 0x20 == 0 {
+        core::hint::spin_loop();
+    }
+
+    outb(0x61, port_b);
+}
+
 impl Timer for PIT {
     fn init(&mut self) {}
 
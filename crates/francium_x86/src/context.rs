@@ -115,18 +115,14 @@ impl X86Regs {
     }
 }
 
-#[repr(C, align(16))]
-#[derive(Debug, Clone)]
-pub struct FXSaveArea {
-    pub fxsave: [u8; 512],
-}
-
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ThreadContext {
     // regs includes kernel sp
     pub regs: X86Regs,
-    pub fxsave: FXSaveArea,
+    // Pointer to the XSAVE (or FXSAVE) area, which is sized at runtime from CPUID.
+    // Must be 64 byte aligned.
+    pub extended_state: usize,
 }
 
 #[repr(C)]
@@ -147,7 +143,7 @@ impl ThreadContext {
     pub const fn new() -> ThreadContext {
         ThreadContext {
             regs: X86Regs::new(),
-            fxsave: FXSaveArea { fxsave: [0; 512] },
+            extended_state: 0,
         }
     }
 }
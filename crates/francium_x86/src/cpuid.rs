@@ -31,3 +31,42 @@ pub fn is_hypervisor_present() -> bool {
 
     (ecx & (1 << 31)) != 0
 }
+
+// Leaf 1 Processor Info and Feature Bits: ecx bit 24
+pub fn has_tsc_deadline() -> bool {
+    let mut ecx: u32;
+    unsafe {
+        asm!("
+			push rbx
+
+		  mov eax, 1
+	      cpuid
+		  mov {ecx:e}, ecx
+		  pop rbx", ecx = out(reg) ecx);
+    }
+
+    (ecx & (1 << 24)) != 0
+}
+
+// Returns eax, ebx, ecx, edx.
+pub fn cpuid(leaf: u32, subleaf: u32) -> (u32, u32, u32, u32) {
+    let eax: u32;
+    let ebx: u32;
+    let ecx: u32;
+    let edx: u32;
+    unsafe {
+        asm!("
+			push rbx
+
+		  cpuid
+		  mov {ebx:e}, ebx
+		  pop rbx", ebx = out(reg) ebx, inout("eax") leaf => eax, inout("ecx") subleaf => ecx, out("edx") edx);
+    }
+
+    (eax, ebx, ecx, edx)
+}
+
+// Leaf 1 Processor Info and Feature Bits: ecx bit 26
+pub fn has_xsave() -> bool {
+    (cpuid(1, 0).2 & (1 << 26)) != 0
+}
@@ -1,6 +1,7 @@
 use core::arch::asm;
 
 const IA32_APIC_BASE: u32 = 0x1b;
+const IA32_TSC_DEADLINE: u32 = 0x6e0;
 
 const IA32_EFER: u32 = 0xc0000080;
 const IA32_STAR: u32 = 0xc0000081;
@@ -87,3 +88,16 @@ pub unsafe fn read_apic_base() -> usize {
 pub unsafe fn write_apic_base(apic_base: usize) {
     write_msr(IA32_APIC_BASE, apic_base)
 }
+
+pub unsafe fn write_tsc_deadline(deadline: usize) {
+    write_msr(IA32_TSC_DEADLINE, deadline)
+}
+
+pub fn read_tsc() -> usize {
+    let mut value_low: u32;
+    let mut value_high: u32;
+    unsafe {
+        asm!("rdtsc", out("eax")(value_low), out("edx")(value_high));
+    }
+    (value_low as usize) | ((value_high as usize) << 32)
+}
@@ -1,4 +1,8 @@
+use syscall_gen_buildtime::generate_kernel;
+
 fn main() {
+    generate_kernel("../syscall_definitions/syscalls.toml");
+
     let mut platform: String = "".to_string();
     let mut found_platform = false;
 
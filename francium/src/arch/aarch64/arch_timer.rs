@@ -9,6 +9,14 @@ impl ArchTimer {
     pub fn new() -> ArchTimer {
         ArchTimer {}
     }
+
+    fn ns_to_ticks(ns: u64) -> u64 {
+        ((ns as u128 * CNTFRQ_EL0.get() as u128) / 1000000000) as u64
+    }
+
+    fn ticks_to_ns(ticks: u64) -> u64 {
+        ((ticks as u128 * 1000000000) / CNTFRQ_EL0.get() as u128) as u64
+    }
 }
 
 static mut TIMER_VAL: u64 = 0;
@@ -19,9 +27,8 @@ impl Timer for ArchTimer {
     fn tick(&mut self) {}
 
     fn set_period_us(&mut self, us: u64) {
-        // TODO: properly do this somehow, or at least with more precision
         unsafe {
-            TIMER_VAL = (CNTFRQ_EL0.get() / 1000000) * us;
+            TIMER_VAL = ArchTimer::ns_to_ticks(us * 1000);
         }
     }
 
@@ -35,10 +42,18 @@ impl Timer for ArchTimer {
         CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET);
     }
 
-    fn get_counter_ns(&self) -> u64 {
-        let val = CNTPCT_EL0.get();
-        let ns_per_tick = (1000000000) / CNTFRQ_EL0.get();
+    fn set_deadline_ns(&mut self, deadline_ns: u64) {
+        // CVAL is an absolute compare value, so a deadline in the past fires straight away.
+        CNTP_CVAL_EL0.set(ArchTimer::ns_to_ticks(deadline_ns));
+        CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
+    }
+
+    fn clear_deadline(&mut self) {
+        // The timer interrupt is level triggered, so mask it until someone asks for another deadline.
+        CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::SET);
+    }
 
-        val * ns_per_tick
+    fn get_counter_ns(&self) -> u64 {
+        ArchTimer::ticks_to_ns(CNTPCT_EL0.get())
     }
 }
@@ -4,6 +4,8 @@ use crate::drivers::InterruptController;
 use crate::drivers::Timer;
 use crate::platform::{DEFAULT_TIMER, INTERRUPT_CONTROLLER};
 use crate::timer;
+use common::os_error::{Module, Reason, ResultCode};
+use common::process::ExitReason;
 
 use aarch64_cpu::registers::*;
 use tock_registers::interfaces::Readable;
@@ -156,6 +158,24 @@ pub extern "C" fn rust_curr_el_spx_sync(ctx: &ExceptionContext) -> ! {
     loop {}
 }
 
+fn user_fault_reason(ec: u64, iss: u64) -> ExitReason {
+    match ec {
+        // Undefined instructions show up as an unknown exception.
+        0b000000 => ExitReason::UndefinedInstruction,
+        0b100000 => ExitReason::PageFault,
+        0b100100 => {
+            if iss & 0x3f == 0b100001 {
+                ExitReason::AlignmentFault
+            } else {
+                ExitReason::PageFault
+            }
+        }
+        0b100010 | 0b100110 => ExitReason::AlignmentFault,
+        0b111100 => ExitReason::Breakpoint,
+        _ => ExitReason::OtherFault,
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn rust_lower_el_spx_sync(ctx: &mut ExceptionContext) {
     let esr = ESR_EL1.get();
@@ -171,7 +191,8 @@ pub extern "C" fn rust_lower_el_spx_sync(ctx: &mut ExceptionContext) {
 
             svc_wrappers::SVC_HANDLERS[iss as usize](ctx);
         } else {
-            panic!("Invalid SVC!");
+            // Not a syscall we have, tell the process rather than taking the kernel down.
+            ctx.regs[0] = ResultCode::new(Module::Kernel, Reason::NotImplemented).0 as usize;
         }
     } else {
         println!("Exception!!! rust_lower_el_spx_sync!\n");
@@ -206,7 +227,24 @@ pub extern "C" fn rust_lower_el_spx_sync(ctx: &mut ExceptionContext) {
             println!("instruction fault status: {}", stringify_ifsc(ifsc));
         }
 
-        loop {}
+        println!("Register dump");
+        for (i, reg) in ctx.regs[..31].iter().enumerate() {
+            println!("x{}: {:016x}", i, reg);
+        }
+        println!("sp: {:016x}", ctx.regs[31]);
+
+        // Only the faulting process goes down, everything else keeps running.
+        let reason = user_fault_reason(ec, iss);
+        {
+            let current_process = crate::scheduler::get_current_process();
+            let proc_locked = current_process.lock();
+            println!(
+                "Terminating process {} ({}): {:?}",
+                proc_locked.name, proc_locked.id, reason
+            );
+        }
+
+        crate::scheduler::terminate_current_process(reason, 0);
     }
 }
 
@@ -221,13 +259,15 @@ pub extern "C" fn rust_lower_el_aarch64_irq(_ctx: &mut ExceptionContext) {
                 // Pi3 timer
                 let mut timer_lock = DEFAULT_TIMER.lock();
                 timer_lock.tick();
-                timer_lock.reset_timer();
+                // Re-armed by timer::tick, if anything is waiting on a deadline.
+                timer_lock.clear_deadline();
                 INTERRUPT_CONTROLLER.lock().ack_interrupt(interrupt);
             }
             30 => {
                 let mut timer_lock = DEFAULT_TIMER.lock();
                 timer_lock.tick();
-                timer_lock.reset_timer();
+                // Re-armed by timer::tick, if anything is waiting on a deadline.
+                timer_lock.clear_deadline();
                 INTERRUPT_CONTROLLER.lock().ack_interrupt(interrupt);
             }
             _ => {
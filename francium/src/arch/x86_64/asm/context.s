@@ -9,6 +9,18 @@ setup_initial_thread_context:
 mov rbx, rdi
 mov rsp, [rbx + 152 + 8]
 
+// Load the initial extended state.
+mov eax, 0xffffffff
+mov edx, 0xffffffff
+mov r8, [rbx + 184]
+cmp byte ptr [rip + EXTENDED_STATE_USE_XSAVE], 0
+je 2f
+xrstor64 [r8]
+jmp 3f
+2:
+fxrstor64 [r8]
+3:
+
 mov rdi, rsi
 call force_unlock_mutex
 
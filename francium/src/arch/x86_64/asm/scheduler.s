@@ -25,8 +25,18 @@ mov [rdi + 136], rax
 // Save SP.
 mov [rdi + 160], rsp
 
-// Save floating point.
-fxsave [rdi + 192]
+// Save extended state. xsave takes its component mask in edx:eax, so stash the from mutex in r9.
+mov r9, rdx
+mov eax, 0xffffffff
+mov edx, 0xffffffff
+mov r8, [rdi + 184]
+cmp byte ptr [rip + EXTENDED_STATE_USE_XSAVE], 0
+je 2f
+xsave64 [r8]
+jmp 3f
+2:
+fxsave64 [r8]
+3:
 
 // Load our new registers.
 mov rbx, [rsi + 8]
@@ -39,8 +49,16 @@ mov r15, [rsi + 112]
 // Restore SP.
 mov rsp, [rsi + 160]
 
-// Restore floating point.
-fxrstor [rdi + 192]
+// Restore extended state.
+mov r8, [rsi + 184]
+cmp byte ptr [rip + EXTENDED_STATE_USE_XSAVE], 0
+je 4f
+xrstor64 [r8]
+jmp 5f
+4:
+fxrstor64 [r8]
+5:
+mov rdx, r9
 
 push rsi
 push rcx
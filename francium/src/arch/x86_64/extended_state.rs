@@ -0,0 +1,75 @@
+use crate::arch::cpuid;
+use alloc::alloc::{alloc_zeroed, dealloc, Layout};
+use core::arch::asm;
+
+// x87, SSE and AVX.
+const SUPPORTED_COMPONENTS: u64 = 0b111;
+
+const FCW_DEFAULT: u16 = 0x37f;
+const MXCSR_DEFAULT: u32 = 0x1f80;
+
+// Read by switch_thread_asm, to pick between xsave and fxsave.
+#[no_mangle]
+static mut EXTENDED_STATE_USE_XSAVE: u8 = 0;
+// fxsave's area is always 512 bytes.
+static mut EXTENDED_STATE_SIZE: usize = 512;
+
+// Needs to run on every CPU, and on the boot CPU before any threads are created.
+pub unsafe fn enable() {
+    // Clear CR0.EM, set CR0.MP, and set CR4.OSFXSR and CR4.OSXMMEXCPT.
+    asm!(
+        "
+		mov rax, cr0
+		and ax, 0xFFFB
+		or ax, 0x2
+		mov cr0, rax
+		mov rax, cr4
+		or ax, 3 << 9
+		mov cr4, rax
+	",
+        out("rax") _
+    );
+
+    if cpuid::has_xsave() {
+        // CR4.OSXSAVE
+        asm!(
+            "
+			mov rax, cr4
+			or rax, 1 << 18
+			mov cr4, rax
+		",
+            out("rax") _
+        );
+
+        let (supported_low, _, _, supported_high) = cpuid::cpuid(0xd, 0);
+        let components =
+            ((supported_low as u64) | ((supported_high as u64) << 32)) & SUPPORTED_COMPONENTS;
+        asm!("xsetbv", in("ecx") 0, in("eax") components as u32, in("edx") (components >> 32) as u32);
+
+        // ebx is the size needed for the components currently enabled in XCR0.
+        let (_, size, _, _) = cpuid::cpuid(0xd, 0);
+        EXTENDED_STATE_USE_XSAVE = 1;
+        EXTENDED_STATE_SIZE = size as usize;
+    }
+}
+
+pub fn alloc_extended_state() -> usize {
+    unsafe {
+        let area = alloc_zeroed(Layout::from_size_align(EXTENDED_STATE_SIZE, 64).unwrap());
+
+        // A zeroed XSAVE header means everything else starts in its init state, but MXCSR is always loaded.
+        (area as *mut u16).write(FCW_DEFAULT);
+        (area.add(24) as *mut u32).write(MXCSR_DEFAULT);
+
+        area as usize
+    }
+}
+
+pub unsafe fn free_extended_state(area: usize) {
+    if area != 0 {
+        dealloc(
+            area as *mut u8,
+            Layout::from_size_align(EXTENDED_STATE_SIZE, 64).unwrap(),
+        );
+    }
+}
@@ -4,6 +4,7 @@ use crate::drivers::InterruptController;
 use crate::drivers::Timer;
 use crate::platform::DEFAULT_TIMER;
 use crate::platform::INTERRUPT_CONTROLLER;
+use common::process::ExitReason;
 use core::arch::{asm, global_asm};
 
 macro_rules! interrupt_noerror {
@@ -244,12 +245,62 @@ pub fn read_cr2() -> usize {
     }
 }
 
+// Exceptions that user code can cause itself.
+// NMI, debug, breakpoint and machine check have nothing to do with what the process did, so they go down the kernel path.
+fn is_user_fault(interrupt_number: u64) -> bool {
+    matches!(
+        interrupt_number,
+        0x0 | 0x4 | 0x5 | 0x6 | 0x7 | 0xd | 0xe | 0x10 | 0x11 | 0x13
+    )
+}
+
+fn user_fault_reason(interrupt_number: u64) -> ExitReason {
+    match interrupt_number {
+        0x0 => ExitReason::DivideError,
+        0x6 => ExitReason::UndefinedInstruction,
+        0xd => ExitReason::GeneralProtection,
+        0xe => ExitReason::PageFault,
+        0x11 => ExitReason::AlignmentFault,
+        _ => ExitReason::OtherFault,
+    }
+}
+
+// A fault in user mode only takes down the process that caused it.
+fn handle_user_fault(ctx: &ExceptionContext, error_code: u64, interrupt_number: u64) -> ! {
+    let reason = user_fault_reason(interrupt_number);
+
+    {
+        let process = crate::scheduler::get_current_process();
+        let process_locked = process.lock();
+        log::error!(
+            "Process {} ({}) faulted: {:?} (interrupt {}, error code {:x})",
+            process_locked.name,
+            process_locked.id,
+            reason,
+            interrupt_number,
+            error_code
+        );
+    }
+
+    if interrupt_number == 0xe {
+        log::error!("Faulting address: {:x}", read_cr2());
+    }
+    log::error!("Register dump\n{:x?}", ctx.regs);
+
+    crate::scheduler::terminate_current_process(reason, 0);
+}
+
 #[no_mangle]
 unsafe extern "C" fn handle_exception(
     ctx: &ExceptionContext,
     error_code: u64,
     interrupt_number: u64,
 ) {
+    let from_user = (ctx.regs.cs & 3) == 3;
+    if from_user && is_user_fault(interrupt_number) {
+        handle_user_fault(ctx, error_code, interrupt_number);
+    }
+
     match interrupt_number {
         0x6 => {
             log::debug!("Invalid instruction!");
@@ -1,4 +1,5 @@
 pub use francium_x86::*;
+pub mod extended_state;
 pub mod gdt;
 pub mod idt;
 pub mod info;
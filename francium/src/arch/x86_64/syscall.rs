@@ -1,4 +1,4 @@
-use crate::arch::x86_64::svc_wrappers::SYSCALL_WRAPPERS;
+use crate::arch::x86_64::svc_wrappers::{syscall_wrapper_unused, SYSCALL_COUNT, SYSCALL_WRAPPERS};
 use core::arch::asm;
 #[naked]
 unsafe extern "C" fn syscall_handler() {
@@ -21,10 +21,19 @@ unsafe extern "C" fn syscall_handler() {
 
 		mov rcx, r10
 
-		lea r11, [rip+{}]
+		// Anything past the end of the table isn't a syscall.
+		cmp rax, {count}
+		jae 2f
+
+		lea r11, [rip+{wrappers}]
 		mov r11, [r11 + rax*8]
 		call r11
+		jmp 3f
+
+	2:
+		call {unused}
 
+	3:
 		pop rbp
 		pop r15
 		pop r14
@@ -41,7 +50,9 @@ unsafe extern "C" fn syscall_handler() {
 
 		sysretq
 	",
-        sym SYSCALL_WRAPPERS,
+        count = const SYSCALL_COUNT,
+        wrappers = sym SYSCALL_WRAPPERS,
+        unused = sym syscall_wrapper_unused,
         options(noreturn)
     );
 }
@@ -6,3 +6,6 @@ pub const KERNEL_HEAP_BASE: usize = 0xfffffffc00000000;
 pub const KERNEL_HEAP_INITIAL_SIZE: usize = 0x2000;
 
 pub const PAGE_SIZE: usize = 0x1000;
+
+// Userspace lives below this, on every architecture we support.
+pub const USER_ADDRESS_LIMIT: usize = 0x0000_8000_0000_0000;
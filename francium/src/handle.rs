@@ -1,5 +1,7 @@
 use alloc::boxed::Box;
 use alloc::sync::Arc;
+use common::handle::HandleRights;
+use common::os_error::ResultCode;
 use spin::Mutex;
 
 use crate::memory::AddressSpace;
@@ -7,20 +9,47 @@ use crate::process::Process;
 use crate::scheduler;
 use crate::svc::event::Event;
 use crate::svc::ipc::{ClientSession, Port, ServerSession};
+use crate::svc::timer::TimerObject;
 
 #[derive(Debug, Clone)]
 pub enum HandleObject {
-    Process(Arc<Mutex<Box<Process>>>),
+    Process(Arc<Mutex<Process>>),
     AddressSpace(Arc<Mutex<Box<AddressSpace>>>),
     Port(Arc<Port>),
     ServerSession(Arc<ServerSession>),
     ClientSession(Arc<ClientSession>),
     Event(Arc<Event>),
+    Timer(Arc<TimerObject>),
     Invalid,
 }
 
-pub fn get_handle(reg: u32) -> HandleObject {
+impl HandleObject {
+    // Called whenever a handle to this object is added to a handle table.
+    pub fn on_handle_opened(&self, rights: HandleRights) {
+        match self {
+            HandleObject::Port(port) => port.add_handle(rights),
+            HandleObject::ServerSession(server_session) => server_session.add_handle(),
+            HandleObject::ClientSession(client_session) => client_session.add_handle(),
+            _ => {}
+        }
+    }
+
+    // Called whenever a handle to this object is closed. This can wake threads, so don't hold a process lock.
+    pub fn on_handle_closed(&self, rights: HandleRights) {
+        match self {
+            HandleObject::Port(port) => port.remove_handle(rights),
+            HandleObject::ServerSession(server_session) => server_session.remove_handle(),
+            HandleObject::ClientSession(client_session) => client_session.remove_handle(),
+            _ => {}
+        }
+    }
+}
+
+pub fn get_handle(reg: u32, rights: HandleRights) -> Result<HandleObject, ResultCode> {
     let process_locked = scheduler::get_current_process();
-    let x = process_locked.lock().handle_table.get_object(reg);
+    let x = process_locked
+        .lock()
+        .handle_table
+        .get_object_checked(reg, rights);
     x
 }
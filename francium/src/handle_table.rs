@@ -1,11 +1,38 @@
 use crate::handle::HandleObject;
-use common::os_error::{Module, Reason, ResultCode, RESULT_OK};
+use alloc::vec::Vec;
+use common::handle::HandleRights;
+use common::os_error::{Module, Reason, ResultCode};
 
-// for now i will just fix the handle table size.
-// todo: dynamic
-const MAX_HANDLES: usize = 256;
+// The table grows on demand, up to this many handles per process.
+const MAX_HANDLES: usize = 1024;
+
+// Handle values are the slot index in the low 16 bits, and the slot's generation in the high 16 bits.
+// Closing a handle bumps the generation, so a stale handle never refers to whatever reuses the slot.
+const INDEX_BITS: u32 = 16;
+const INDEX_MASK: u32 = (1 << INDEX_BITS) - 1;
+
+struct HandleEntry {
+    generation: u16,
+    object: HandleObject,
+    rights: HandleRights,
+}
+
+// Dropping a table closes every handle in it, which can wake other threads. Don't drop one with a process locked.
 pub struct HandleTable {
-    handles: [HandleObject; MAX_HANDLES],
+    entries: Vec<HandleEntry>,
+}
+
+// A handle that was just removed from a table. The object finds out when this is dropped,
+// which can wake other threads, so let the process lock go first.
+pub struct ClosedHandle {
+    object: HandleObject,
+    rights: HandleRights,
+}
+
+impl Drop for ClosedHandle {
+    fn drop(&mut self) {
+        self.object.on_handle_closed(self.rights);
+    }
 }
 
 impl core::fmt::Debug for HandleTable {
@@ -14,46 +41,130 @@ impl core::fmt::Debug for HandleTable {
     }
 }
 
+fn make_handle(index: usize, generation: u16) -> u32 {
+    ((generation as u32) << INDEX_BITS) | index as u32
+}
+
 impl HandleTable {
     pub fn new() -> HandleTable {
-        const INVALID_HANDLE: HandleObject = HandleObject::Invalid;
         HandleTable {
-            handles: [INVALID_HANDLE; MAX_HANDLES],
+            entries: Vec::new(),
+        }
+    }
+
+    fn get_entry(&self, handle: u32) -> Option<&HandleEntry> {
+        let index = (handle & INDEX_MASK) as usize;
+        let generation = (handle >> INDEX_BITS) as u16;
+
+        match self.entries.get(index) {
+            Some(entry) if entry.generation == generation => Some(entry),
+            _ => None,
         }
     }
 
     pub fn get_object(&self, handle: u32) -> HandleObject {
-        if (handle as usize) < MAX_HANDLES {
-            self.handles[handle as usize].clone()
-        } else {
-            HandleObject::Invalid
+        match self.get_entry(handle) {
+            Some(entry) => entry.object.clone(),
+            None => HandleObject::Invalid,
         }
     }
 
-    pub fn get_handle(&mut self, handle_obj: HandleObject) -> u32 {
-        for (index, obj) in self.handles.iter().enumerate() {
-            match obj {
+    // Like get_object, but fails with NotAllowed if the handle is missing any of the required rights.
+    pub fn get_object_checked(
+        &self,
+        handle: u32,
+        required: HandleRights,
+    ) -> Result<HandleObject, ResultCode> {
+        match self.get_entry(handle) {
+            Some(entry) => match entry.object {
                 HandleObject::Invalid => {
-                    self.handles[index] = handle_obj;
-                    return index as u32;
+                    Err(ResultCode::new(Module::Kernel, Reason::InvalidHandle))
                 }
-                _ => continue,
+                _ if !entry.rights.contains(required) => {
+                    Err(ResultCode::new(Module::Kernel, Reason::NotAllowed))
+                }
+                _ => Ok(entry.object.clone()),
+            },
+            None => Err(ResultCode::new(Module::Kernel, Reason::InvalidHandle)),
+        }
+    }
+
+    pub fn get_rights(&self, handle: u32) -> HandleRights {
+        match self.get_entry(handle) {
+            Some(entry) => entry.rights,
+            None => HandleRights::NONE,
+        }
+    }
+
+    pub fn get_handle(&mut self, handle_obj: HandleObject) -> Result<u32, ResultCode> {
+        self.get_handle_with_rights(handle_obj, HandleRights::ALL)
+    }
+
+    pub fn get_handle_with_rights(
+        &mut self,
+        handle_obj: HandleObject,
+        rights: HandleRights,
+    ) -> Result<u32, ResultCode> {
+        for (index, entry) in self.entries.iter_mut().enumerate() {
+            if let HandleObject::Invalid = entry.object {
+                handle_obj.on_handle_opened(rights);
+                entry.object = handle_obj;
+                entry.rights = rights;
+                return Ok(make_handle(index, entry.generation));
             }
         }
-        panic!("handle table is exhausted!");
+
+        if self.entries.len() >= MAX_HANDLES {
+            return Err(ResultCode::new(Module::Kernel, Reason::LimitReached));
+        }
+
+        // Start at generation 1, so 0 is never a valid handle.
+        let index = self.entries.len();
+        handle_obj.on_handle_opened(rights);
+        self.entries.push(HandleEntry {
+            generation: 1,
+            object: handle_obj,
+            rights: rights,
+        });
+        Ok(make_handle(index, 1))
     }
 
-    pub fn close(&mut self, handle: u32) -> ResultCode {
-        if (handle as usize) < MAX_HANDLES {
-            match self.handles[handle as usize] {
-                HandleObject::Invalid => ResultCode::new(Module::Kernel, Reason::InvalidHandle),
-                _ => {
-                    self.handles[handle as usize] = HandleObject::Invalid;
-                    RESULT_OK
-                }
+    // Makes a new handle to the same object, with at most the rights of the original.
+    pub fn duplicate(&mut self, handle: u32, rights: HandleRights) -> Result<u32, ResultCode> {
+        let obj = self.get_object_checked(handle, HandleRights::DUPLICATE)?;
+        if !self.get_rights(handle).contains(rights) {
+            return Err(ResultCode::new(Module::Kernel, Reason::NotAllowed));
+        }
+
+        self.get_handle_with_rights(obj, rights)
+    }
+
+    pub fn close(&mut self, handle: u32) -> Result<ClosedHandle, ResultCode> {
+        if self.get_entry(handle).is_none() {
+            return Err(ResultCode::new(Module::Kernel, Reason::InvalidHandle));
+        }
+
+        let entry = &mut self.entries[(handle & INDEX_MASK) as usize];
+        match entry.object {
+            HandleObject::Invalid => Err(ResultCode::new(Module::Kernel, Reason::InvalidHandle)),
+            _ => {
+                entry.generation = match entry.generation.wrapping_add(1) {
+                    0 => 1,
+                    generation => generation,
+                };
+                Ok(ClosedHandle {
+                    object: core::mem::replace(&mut entry.object, HandleObject::Invalid),
+                    rights: core::mem::replace(&mut entry.rights, HandleRights::NONE),
+                })
             }
-        } else {
-            ResultCode::new(Module::Kernel, Reason::InvalidHandle)
+        }
+    }
+}
+
+impl Drop for HandleTable {
+    fn drop(&mut self) {
+        for entry in self.entries.iter() {
+            entry.object.on_handle_closed(entry.rights);
         }
     }
 }
@@ -428,6 +428,7 @@ static mut PER_CPU_SINGLE_CORE: PerCpuData = PerCpuData {
     saved_kernel_stack: 0,
     current_thread: None,
     idle_thread: None,
+    is_boot_cpu: true,
 
     #[cfg(target_arch = "x86_64")]
     gdt: [GDTEntry::DEFAULT; 8],
@@ -451,6 +452,7 @@ pub fn setup_ap_per_cpu(cpu_num: usize) {
             saved_kernel_stack: 0,
             current_thread: None,
             idle_thread: Some(crate::scheduler::get_idle_thread(cpu_num)),
+            is_boot_cpu: false,
             #[cfg(target_arch = "x86_64")]
             gdt: [GDTEntry::DEFAULT; 8],
             #[cfg(target_arch = "x86_64")]
@@ -31,6 +31,7 @@ pub mod process;
 pub mod scheduler;
 pub mod svc;
 pub mod timer;
+pub mod user_ptr;
 pub mod waitable;
 
 pub mod init;
@@ -1,5 +1,7 @@
-use crate::mmu::{MapType, PagePermission, PageTable};
+use crate::constants::{PAGE_SIZE, USER_ADDRESS_LIMIT};
+use crate::mmu::{phys_to_virt, MapType, PagePermission, PageTable};
 use crate::phys_allocator;
+use common::os_error::{Module, Reason, ResultCode};
 use francium_common::types::PhysAddr;
 use smallvec::SmallVec;
 use spin::RwLock;
@@ -16,6 +18,8 @@ pub struct Block {
     pub address: usize,
     pub size: usize,
     pub permissions: PagePermission,
+    // Aliased memory might be mapped into more than one address space.
+    pub shared: bool,
 }
 
 pub struct AddressSpace {
@@ -47,12 +51,19 @@ fn reprotect_region(pg: &mut PageTable, start_addr: usize, size: usize, perm: Pa
 
 impl AddressSpace {
     pub fn new(template_page_table: PageTable) -> AddressSpace {
+        match AddressSpace::try_new(template_page_table) {
+            Ok(aspace) => aspace,
+            Err(_) => panic!("Out of physical memory!"),
+        }
+    }
+
+    pub fn try_new(template_page_table: PageTable) -> Result<AddressSpace, ResultCode> {
         // Crimes activated
         // This will only really work if pagetable is exactly a page big... and we never free it.
         unsafe {
             let phys_page = match phys_allocator::alloc() {
                 Some(x) => x,
-                None => panic!("Out of physical memory!"),
+                None => return Err(ResultCode::new(Module::Kernel, Reason::OutOfMemory)),
             };
 
             let page_table_ptr = crate::mmu::phys_to_virt(phys_page) as *mut PageTable;
@@ -63,11 +74,11 @@ impl AddressSpace {
                 None => panic!("Somehow phys_to_virt returned null?"),
             };
 
-            AddressSpace {
+            Ok(AddressSpace {
                 page_table: page_table,
                 page_table_phys: phys_page,
                 regions: SmallVec::new(),
-            }
+            })
         }
     }
 
@@ -88,6 +99,7 @@ impl AddressSpace {
             address: start_addr,
             size: size,
             permissions: perm,
+            shared: true,
         })
     }
 
@@ -113,7 +125,7 @@ impl AddressSpace {
 
                 // Need to map a chunk from found region end to new region end.
                 map_region(&mut self.page_table, start_addr + overlap, deficit, perm);
-                reg.size = size;
+                reg.size = start_addr + size - reg.address;
 
                 found_overlap = true;
             }
@@ -129,28 +141,79 @@ impl AddressSpace {
     }
 
     pub fn create(&mut self, start_addr: usize, size: usize, perm: PagePermission) {
-        assert!(start_addr & 0xfff == 0);
-        assert!(size & 0xfff == 0);
-
-        for reg in self.regions.iter() {
-            if (reg.address >= start_addr && reg.address <= start_addr + size)
-                || (reg.address + reg.size > start_addr
-                    && reg.address + reg.size <= start_addr + size)
-            {
-                panic!(
-                    "Overlapping regions! {:x} {:x} {:x} {:x}",
-                    reg.address, reg.size, start_addr, size
-                );
+        if let Err(res) = self.try_create(start_addr, size, perm) {
+            panic!(
+                "Couldn't create region {:x} {:x}: {:?}",
+                start_addr, size, res
+            );
+        }
+    }
+
+    // Maps fresh memory at start_addr. On failure nothing is mapped.
+    // InvalidArgument if the range is misaligned or overlaps another region, OutOfMemory if we ran out of pages.
+    pub fn try_create(
+        &mut self,
+        start_addr: usize,
+        size: usize,
+        perm: PagePermission,
+    ) -> Result<(), ResultCode> {
+        let end_addr = match start_addr.checked_add(size) {
+            Some(end_addr) => end_addr,
+            None => return Err(ResultCode::new(Module::Kernel, Reason::InvalidArgument)),
+        };
+
+        if start_addr & 0xfff != 0 || size & 0xfff != 0 || size == 0 {
+            return Err(ResultCode::new(Module::Kernel, Reason::InvalidArgument));
+        }
+
+        if self
+            .regions
+            .iter()
+            .any(|reg| reg.address < end_addr && start_addr < reg.address + reg.size)
+        {
+            return Err(ResultCode::new(Module::Kernel, Reason::InvalidArgument));
+        }
+
+        // Get all the pages up front, so running out part way doesn't leave half a region mapped.
+        // Until they're mapped, each page holds the address of the one allocated before it.
+        let mut pages: Option<PhysAddr> = None;
+        for _ in 0..size / PAGE_SIZE {
+            match unsafe { phys_allocator::alloc() } {
+                Some(page) => unsafe {
+                    phys_allocator::write_phys(page, pages);
+                    pages = Some(page);
+                },
+                None => {
+                    while let Some(page) = pages {
+                        unsafe {
+                            pages = phys_allocator::read_phys(page);
+                            phys_allocator::free(page);
+                        }
+                    }
+                    return Err(ResultCode::new(Module::Kernel, Reason::OutOfMemory));
+                }
             }
         }
 
-        map_region(&mut self.page_table, start_addr, size, perm);
+        let mut addr = end_addr;
+        while let Some(page) = pages {
+            unsafe {
+                pages = phys_allocator::read_phys(page);
+                phys_allocator::write_phys::<Option<PhysAddr>>(page, None);
+            }
+            addr -= PAGE_SIZE;
+            self.page_table
+                .map_4k(page, addr, perm, MapType::NormalCachable);
+        }
 
         self.regions.push(Block {
             address: start_addr,
             size: size,
             permissions: perm,
-        })
+            shared: false,
+        });
+
+        Ok(())
     }
 
     pub fn expand(&mut self, start_addr: usize, new_size: usize) {
@@ -184,6 +247,101 @@ impl AddressSpace {
         panic!("Wtf?");
     }
 
+    pub fn is_shared(&self, addr: usize) -> bool {
+        self.regions
+            .iter()
+            .any(|r| r.shared && addr >= r.address && addr < r.address + r.size)
+    }
+
+    // Whether all of the range is user memory mapped in this address space, and writable if write is set.
+    // The range can cross from one region into the next, as long as there's no gap.
+    pub fn can_access_user(&self, address: usize, length: usize, write: bool) -> bool {
+        let end = match address.checked_add(length) {
+            Some(end) if end <= USER_ADDRESS_LIMIT => end,
+            _ => return false,
+        };
+
+        let mut current = address;
+        while current < end {
+            let region = self
+                .regions
+                .iter()
+                .find(|r| current >= r.address && current < r.address + r.size);
+
+            match region {
+                Some(r)
+                    if !r.permissions.contains(PagePermission::KERNEL)
+                        && (!write || r.permissions.contains(PagePermission::WRITE)) =>
+                {
+                    current = r.address + r.size;
+                }
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    // Copies out of this address space through the physmap a page at a time, so it doesn't have to be the active one.
+    // Returns false if any of the range isn't mapped.
+    pub fn read_bytes(&self, address: usize, buf: &mut [u8]) -> bool {
+        let mut offset = 0;
+        while offset < buf.len() {
+            let src = match address.checked_add(offset) {
+                Some(src) => src,
+                None => return false,
+            };
+            let chunk_len = core::cmp::min(PAGE_SIZE - (src & (PAGE_SIZE - 1)), buf.len() - offset);
+
+            let src_virt = match self.page_table.virt_to_phys(src) {
+                Some(phys) => phys_to_virt(phys),
+                None => return false,
+            };
+
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    src_virt as *const u8,
+                    buf[offset..].as_mut_ptr(),
+                    chunk_len,
+                );
+            }
+
+            offset += chunk_len;
+        }
+
+        true
+    }
+
+    // Same as read_bytes, but the other way.
+    pub fn write_bytes(&mut self, address: usize, buf: &[u8]) -> bool {
+        let mut offset = 0;
+        while offset < buf.len() {
+            let dest = match address.checked_add(offset) {
+                Some(dest) => dest,
+                None => return false,
+            };
+            let chunk_len =
+                core::cmp::min(PAGE_SIZE - (dest & (PAGE_SIZE - 1)), buf.len() - offset);
+
+            let dest_virt = match self.page_table.virt_to_phys(dest) {
+                Some(phys) => phys_to_virt(phys),
+                None => return false,
+            };
+
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    buf[offset..].as_ptr(),
+                    dest_virt as *mut u8,
+                    chunk_len,
+                );
+            }
+
+            offset += chunk_len;
+        }
+
+        true
+    }
+
     pub fn make_active(&self) {
         unsafe {
             arch::mmu::switch_to_page_table(self.page_table_phys);
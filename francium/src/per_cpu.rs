@@ -18,6 +18,8 @@ pub struct PerCpuData {
     pub tss: TSS,
     pub current_thread: Option<Arc<Thread>>,
     pub idle_thread: Option<Arc<Thread>>,
+    // Only the boot CPU runs the scheduler's timer for now.
+    pub is_boot_cpu: bool,
 }
 
 const _: () = assert!(core::mem::size_of::<PerCpuData>() <= 0x1000);
@@ -11,7 +11,7 @@ struct PhysEntry {
     next: Option<PhysAddr>,
 }
 
-unsafe fn read_phys<T: Copy>(addr: PhysAddr) -> T {
+pub unsafe fn read_phys<T: Copy>(addr: PhysAddr) -> T {
     let virt_addr = phys_to_virt(addr);
     *(virt_addr as *const T)
 }
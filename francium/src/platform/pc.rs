@@ -1,35 +1,20 @@
 use crate::arch::msr;
 use crate::drivers::pc_io_apic::IoApic;
-use crate::drivers::pc_local_apic::LocalApic;
+use crate::drivers::pc_local_apic::{LocalApic, LocalApicTimer};
 use crate::drivers::pc_uart::COMPort;
-use crate::drivers::pit_timer::PIT;
 use crate::drivers::Timer;
 use crate::drivers::{InterruptController, InterruptDistributor};
 use crate::mmu;
 use acpi::platform::ProcessorState::WaitingForSipi;
 use alloc::alloc::{alloc_zeroed, Layout};
 use alloc::vec::Vec;
-use core::arch::asm;
+use common::system_info::FirmwareTable;
 use francium_common::types::PhysAddr;
 use spin::Mutex;
 
 pub const PHYS_MEM_BASE: usize = 0;
 pub const PHYS_MEM_SIZE: usize = 0x80000000; // 2gb?? for now
 
-unsafe fn turn_on_floating_point() {
-    asm!(
-        "
-		mov rax, cr0
-		and ax, 0xFFFB
-		or ax, 0x2
-		mov cr0, rax
-		mov rax, cr4
-		or ax, 3 << 9
-		mov cr4, rax
-	"
-    );
-}
-
 use acpi::{AcpiHandler, AcpiTables, PhysicalMapping};
 use core::ptr::NonNull;
 
@@ -55,7 +40,14 @@ impl AcpiHandler for FranciumACPIHandler {
 
 lazy_static! {
     pub static ref DEFAULT_UART: Mutex<COMPort> = Mutex::new(COMPort::new(0x3f8));
-    pub static ref DEFAULT_TIMER: Mutex<PIT> = Mutex::new(PIT::new());
+    // The local APIC timer fires on the old PIT vector, so the interrupt handler doesn't need to know which one it is.
+    pub static ref DEFAULT_TIMER: Mutex<LocalApicTimer> = {
+        if let acpi::platform::interrupt::InterruptModel::Apic(apic_model) = &PLATFORM_INFO.interrupt_model {
+            Mutex::new(LocalApicTimer::new(crate::constants::PERIPHERAL_BASE + apic_model.local_apic_address as usize, 32 + 2))
+        } else {
+            panic!("No apic?");
+        }
+    };
 
     //pub static ref INTERRUPT_CONTROLLER: Mutex<PIC> = Mutex::new(PIC::new());
     //pub static ref INTERRUPT_DISTRIBUTOR: Mutex<PICDist> = Mutex::new(PICDist::new());
@@ -91,24 +83,26 @@ lazy_static! {
 pub fn platform_specific_init() {}
 
 pub fn scheduler_pre_init() {
-    // enable timer irq
-    let timer_irq = 2; // PIC on IRQ 2...
+    // Threads size their extended state from this, so it has to happen before any get created.
+    unsafe {
+        crate::arch::extended_state::enable();
+    }
+
     let mut controller_lock = INTERRUPT_CONTROLLER.lock();
     let mut distributor_lock = INTERRUPT_DISTRIBUTOR.lock();
 
     controller_lock.init();
     distributor_lock.init();
-    distributor_lock.enable_interrupt(timer_irq);
 
-    // enable arch timer, 100hz
+    // The timer is one-shot, and only armed when something needs a deadline.
     let mut timer_lock = DEFAULT_TIMER.lock();
-    timer_lock.set_period_us(10000);
-    timer_lock.reset_timer();
+    timer_lock.init();
 }
 
 pub fn scheduler_post_init() {
+    // Again for the APs, which come through here too.
     unsafe {
-        turn_on_floating_point();
+        crate::arch::extended_state::enable();
     }
 
     // XXX give this a constant
@@ -195,6 +189,13 @@ pub fn bringup_other_cpus() {
     }
 }
 
+pub fn get_firmware_table() -> Option<FirmwareTable> {
+    unsafe {
+        crate::arch::x86_64::info::SYSTEM_INFO_RSDP_ADDR
+            .map(|rsdp_addr| FirmwareTable::AcpiRsdp(rsdp_addr as usize))
+    }
+}
+
 pub fn get_cpu_count() -> usize {
     let processor_info = PLATFORM_INFO.processor_info.as_ref().unwrap();
     processor_info.application_processors.len() + 1
@@ -3,6 +3,7 @@ use crate::constants::*;
 use crate::drivers::bcm_interrupt::*;
 use crate::drivers::pl011_uart::Pl011Uart;
 use crate::drivers::{InterruptController, InterruptDistributor, Timer};
+use common::system_info::FirmwareTable;
 use spin::Mutex;
 
 // TODO: we need multiple interrupt controllers to do this properly
@@ -102,14 +103,13 @@ pub fn scheduler_pre_init() {
 
     distributor_lock.enable_interrupt(timer_irq);
 
-    // enable arch timer, 100hz
+    // The arch timer is one-shot, and only armed when something needs a deadline.
     let mut timer_lock = DEFAULT_TIMER.lock();
-    timer_lock.set_period_us(10000);
-    timer_lock.reset_timer();
+    timer_lock.init();
 }
 
 pub fn scheduler_post_init() {
-    DEFAULT_TIMER.lock().enable_timer();
+    // Nothing to enable, timer.rs arms the first deadline.
 }
 
 pub fn bringup_other_cpus() {}
@@ -117,6 +117,11 @@ pub fn bringup_other_cpus() {}
 use core::arch::global_asm;
 global_asm!(include_str!("../arch/aarch64/asm/stub_raspi3.s"));
 
+// The firmware does hand us a device tree, but we don't know where yet.
+pub fn get_firmware_table() -> Option<FirmwareTable> {
+    None
+}
+
 pub fn get_cpu_count() -> usize {
     1
 }
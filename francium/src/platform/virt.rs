@@ -4,6 +4,7 @@ use crate::drivers::arm_gicv2::*;
 use crate::drivers::pl011_uart::Pl011Uart;
 use crate::drivers::Timer;
 use crate::drivers::{InterruptController, InterruptDistributor};
+use common::system_info::FirmwareTable;
 use spin::Mutex;
 
 const VIRT_GICD_BASE: usize = constants::PERIPHERAL_BASE + 0x08000000;
@@ -34,14 +35,13 @@ pub fn scheduler_pre_init() {
     let mut gicc_lock = INTERRUPT_CONTROLLER.lock();
     gicc_lock.init();
 
-    // enable arch timer, 100hz
+    // The arch timer is one-shot, and only armed when something needs a deadline.
     let mut timer_lock = DEFAULT_TIMER.lock();
-    timer_lock.set_period_us(10000);
-    timer_lock.reset_timer();
+    timer_lock.init();
 }
 
 pub fn scheduler_post_init() {
-    DEFAULT_TIMER.lock().enable_timer();
+    // Nothing to enable, timer.rs arms the first deadline.
 }
 
 pub fn bringup_other_cpus() {}
@@ -49,6 +49,11 @@ pub fn bringup_other_cpus() {}
 use core::arch::global_asm;
 global_asm!(include_str!("../arch/aarch64/asm/stub_virt.s"));
 
+// Qemu puts the device tree at the start of RAM.
+pub fn get_firmware_table() -> Option<FirmwareTable> {
+    Some(FirmwareTable::DeviceTree(PHYS_MEM_BASE))
+}
+
 pub fn get_cpu_count() -> usize {
     1
 }
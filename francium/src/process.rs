@@ -1,11 +1,17 @@
 use crate::arch::context::ThreadContext;
+use crate::handle::HandleObject;
 use crate::handle_table::HandleTable;
 use crate::memory::AddressSpace;
+use crate::svc::futex::FutexKey;
+use crate::waitable::Waiter;
 
 use alloc::alloc::{alloc, Layout};
 use alloc::boxed::Box;
+use alloc::string::{String, ToString};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use atomic_enum::atomic_enum;
+use common::process::ExitReason;
 use core::sync::atomic::Ordering;
 use core::sync::atomic::{AtomicBool, AtomicUsize};
 use spin::Mutex;
@@ -19,6 +25,7 @@ pub enum ThreadState {
     Created,
     Runnable,
     Suspended,
+    Terminated,
 }
 
 pub struct Thread {
@@ -37,6 +44,15 @@ pub struct Thread {
 
     pub is_idle_thread: AtomicBool,
     pub last_svc_number: AtomicUsize,
+
+    // Set while blocked in wait_handles, so timeouts and cancel_wait know it's safe to wake us.
+    pub in_cancellable_wait: AtomicBool,
+    // What wait_handles is blocked on, so an interrupted wait can be taken off all of it.
+    pub wait_objects: Mutex<Vec<HandleObject>>,
+    // A cancel_wait that arrived while we weren't waiting, which cancels the next wait instead.
+    pub cancel_pending: AtomicBool,
+    // The futex we're blocked on, if any. Requeues move it, so a timeout knows where to find us.
+    pub futex_key: Mutex<Option<FutexKey>>,
 }
 
 intrusive_adapter!(pub ThreadProcessAdapter = Arc<Thread>: Thread { process_link: LinkedListAtomicLink });
@@ -62,7 +78,13 @@ pub struct Process {
     pub address_space: AddressSpace,
     pub threads: LinkedList<ThreadProcessAdapter>,
     pub handle_table: HandleTable,
-    pub name: &'static str,
+    pub name: String,
+    // Set once the process starts tearing down.
+    pub exit_reason: Option<ExitReason>,
+    // Only meaningful if the process exited normally.
+    pub exit_code: i32,
+    // Woken when the process exits.
+    pub exit_waiter: Waiter,
 }
 
 intrusive_adapter!(ProcessAdapter = Box<Process>: Process { all_processes_link: LinkedListAtomicLink });
@@ -72,39 +94,69 @@ static THREAD_ID: AtomicUsize = AtomicUsize::new(0);
 
 impl Thread {
     pub fn new(process: Arc<Mutex<Process>>) -> Arc<Thread> {
+        let thread = Thread::new_unattached(process.clone());
+        process.lock().threads.push_back(thread.clone());
+        thread
+    }
+
+    // Doesn't add the thread to the process's list, for callers that need to check the process first.
+    pub fn new_unattached(process: Arc<Mutex<Process>>) -> Arc<Thread> {
         let kernel_stack_size = 0x1000;
 
         let kernel_stack =
             unsafe { alloc(Layout::from_size_align(kernel_stack_size, 0x1000).unwrap()) };
 
+        #[allow(unused_mut)]
+        let mut context = ThreadContext::new();
+        #[cfg(target_arch = "x86_64")]
+        {
+            context.extended_state = crate::arch::extended_state::alloc_extended_state();
+        }
+
         let thread = Arc::new(Thread {
             all_threads_link: LinkedListAtomicLink::new(),
             running_link: LinkedListAtomicLink::new(),
             process_link: LinkedListAtomicLink::new(),
             id: THREAD_ID.fetch_add(1, Ordering::SeqCst),
             state: AtomicThreadState::new(ThreadState::Created),
-            context: Mutex::new(ThreadContext::new()),
-            process: process.clone(),
+            context: Mutex::new(context),
+            process,
             kernel_stack_top: kernel_stack as *const usize as usize + kernel_stack_size,
             kernel_stack_size: kernel_stack_size,
             is_idle_thread: AtomicBool::new(false),
             last_svc_number: AtomicUsize::new(0),
+            in_cancellable_wait: AtomicBool::new(false),
+            wait_objects: Mutex::new(Vec::new()),
+            cancel_pending: AtomicBool::new(false),
+            futex_key: Mutex::new(None),
         });
 
-        process.lock().threads.push_back(thread.clone());
         thread
     }
 }
 
+impl Drop for Thread {
+    fn drop(&mut self) {
+        // Nothing can be running on the thread any more, so nobody will save into its extended state again.
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            crate::arch::extended_state::free_extended_state(self.context.get_mut().extended_state);
+        }
+    }
+}
+
 impl Process {
-    pub fn new(name: &'static str, aspace: AddressSpace) -> Process {
+    pub fn new(name: &str, aspace: AddressSpace) -> Process {
         let p = Process {
             all_processes_link: LinkedListAtomicLink::new(),
             address_space: aspace,
             threads: LinkedList::new(ThreadProcessAdapter::new()),
             id: PROCESS_ID.fetch_add(1, Ordering::SeqCst),
             handle_table: HandleTable::new(),
-            name: name,
+            name: name.to_string(),
+            exit_reason: None,
+            exit_code: 0,
+            exit_waiter: Waiter::new(),
         };
 
         p
@@ -4,12 +4,14 @@ use core::sync::atomic::Ordering;
 use spin::{Mutex, MutexGuard};
 
 use crate::arch::context::ThreadContext;
-use crate::process::{Process, Thread, ThreadState};
+use crate::handle_table::HandleTable;
+use crate::process::{Process, Thread, ThreadProcessAdapter, ThreadState};
 
 use intrusive_collections::intrusive_adapter;
 use intrusive_collections::{LinkedList, LinkedListAtomicLink};
 
 use alloc::vec::Vec;
+use common::process::ExitReason;
 use log::trace;
 
 intrusive_adapter!(pub ThreadAdapter = Arc<Thread>: Thread { all_threads_link: LinkedListAtomicLink });
@@ -68,6 +70,25 @@ impl Scheduler {
         }
     }
 
+    fn has_multiple_runnable(&self) -> bool {
+        let mut iter = self.runnable_threads.iter();
+        iter.next().is_some() && iter.next().is_some()
+    }
+
+    // There's no periodic tick, so make sure a preemption deadline is armed whenever there's something to switch to.
+    fn update_preemption(&self) {
+        let on_idle_thread = crate::per_cpu::get()
+            .current_thread
+            .as_ref()
+            .map_or(false, |t| t.is_idle_thread.load(Ordering::Acquire));
+
+        if on_idle_thread && !self.runnable_threads.is_empty() {
+            crate::timer::preempt_now();
+        } else {
+            crate::timer::update_preemption(self.has_multiple_runnable());
+        }
+    }
+
     fn switch_thread(&mut self, from: &Arc<Thread>, to: &Arc<Thread>) -> usize {
         trace!("Switch from {} to {}", from.id, to.id);
 
@@ -77,6 +98,13 @@ impl Scheduler {
             return 0;
         }
 
+        // Only the boot CPU runs user threads, see terminate_process.
+        assert!(
+            crate::per_cpu::get().is_boot_cpu || to.is_idle_thread.load(Ordering::Acquire),
+            "Tried to run thread {} on an AP",
+            to.id
+        );
+
         let idle_thread = crate::per_cpu::get().idle_thread.as_ref().unwrap();
         // TODO: see comment in wake, this kind of sucks
         if from.id == idle_thread.id {
@@ -88,6 +116,7 @@ impl Scheduler {
         }
 
         crate::per_cpu::set_current_thread(to.clone());
+        crate::timer::restart_timeslice(self.has_multiple_runnable());
 
         // TODO: wow, this sucks
         {
@@ -174,6 +203,30 @@ impl Scheduler {
         }
     }
 
+    // Takes the current thread off the runnable list, and returns the thread to run next.
+    fn remove_current_thread(&mut self, current_thread: &Arc<Thread>) -> Arc<Thread> {
+        // Safety: thread is runnable and not an idle thread
+        let mut cursor = unsafe {
+            self.runnable_threads
+                .cursor_mut_from_ptr(Arc::<Thread>::as_ptr(current_thread))
+        };
+
+        // Cursor now points to old thread
+        cursor.remove();
+        if cursor.is_null() {
+            cursor.move_next();
+
+            // If list is empty, it will still be on the null element.
+            if cursor.is_null() {
+                crate::per_cpu::get().idle_thread.as_ref().unwrap().clone()
+            } else {
+                cursor.as_cursor().clone_pointer().unwrap()
+            }
+        } else {
+            cursor.as_cursor().clone_pointer().unwrap()
+        }
+    }
+
     pub fn suspend(&mut self, thread: &Arc<Thread>) -> usize {
         if thread.state.load(Ordering::Acquire) == ThreadState::Runnable {
             thread
@@ -193,26 +246,7 @@ impl Scheduler {
                 panic!("Tried to suspend an idle thread");
             }
 
-            // Safety: thread is runnable and not an idle thread
-            let mut cursor = unsafe {
-                self.runnable_threads
-                    .cursor_mut_from_ptr(Arc::<Thread>::as_ptr(&current_thread))
-            };
-
-            // Cursor now points to old thread
-            cursor.remove();
-            let next_thread = if cursor.is_null() {
-                cursor.move_next();
-
-                // If list is empty, it will still be on the null element.
-                if cursor.is_null() {
-                    crate::per_cpu::get().idle_thread.as_ref().unwrap().clone()
-                } else {
-                    cursor.as_cursor().clone_pointer().unwrap()
-                }
-            } else {
-                cursor.as_cursor().clone_pointer().unwrap()
-            };
+            let next_thread = self.remove_current_thread(&current_thread);
 
             // If we got switched out, switch to the new current thread.
             if current_id == thread.id {
@@ -229,7 +263,11 @@ impl Scheduler {
     }
 
     pub fn wake(&mut self, thread: &Arc<Thread>, tag: usize) {
-        if thread.state.load(Ordering::Acquire) != ThreadState::Runnable {
+        let state = thread.state.load(Ordering::Acquire);
+        if state == ThreadState::Terminated {
+            // Waiters and timers can still hold a reference to a dead thread, ignore them.
+            trace!("Trying to wake terminated thread {:?}!", thread.id);
+        } else if state != ThreadState::Runnable {
             trace!(
                 "Waking thread {:?} ({})",
                 thread.id,
@@ -240,6 +278,7 @@ impl Scheduler {
             // set x0 of the thread context
             set_thread_context_tag(thread, tag);
             self.runnable_threads.push_back(thread.clone());
+            self.update_preemption();
 
             // TODO: I tried to add an optimization to immediately suspend an idle thread if its running.
             // but calling switch_thread in wake breaks things pretty badly
@@ -253,21 +292,54 @@ impl Scheduler {
         }
     }
 
-    pub fn terminate_current_thread(&mut self) {
+    // Stops a thread that isn't currently running. It will never run again, even if something tries to wake it.
+    pub fn terminate_thread(&mut self, thread: &Arc<Thread>) {
+        thread
+            .state
+            .store(ThreadState::Terminated, Ordering::Release);
+
+        // Safety: the links are only ever used for these lists.
+        if thread.running_link.is_linked() {
+            unsafe {
+                self.runnable_threads
+                    .cursor_mut_from_ptr(Arc::<Thread>::as_ptr(thread))
+                    .remove();
+            }
+        }
+
+        if thread.all_threads_link.is_linked() {
+            unsafe {
+                self.threads
+                    .cursor_mut_from_ptr(Arc::<Thread>::as_ptr(thread))
+                    .remove();
+            }
+        }
+
+        self.update_preemption();
+    }
+
+    pub fn terminate_current_thread(&mut self) -> ! {
         let current_thread = crate::per_cpu::get_current_thread();
 
         if current_thread.is_idle_thread.load(Ordering::Acquire) {
             panic!("Tried to terminate an idle thread");
         }
 
+        current_thread
+            .state
+            .store(ThreadState::Terminated, Ordering::Release);
+
         // Safety: Current thread is a thread
         let mut cursor = unsafe {
             self.threads
                 .cursor_mut_from_ptr(Arc::<Thread>::as_ptr(&current_thread))
         };
-        let this_thread = cursor.remove().unwrap();
+        cursor.remove();
+
+        let next_thread = self.remove_current_thread(&current_thread);
+        self.switch_thread(&current_thread, &next_thread);
 
-        self.suspend(&this_thread);
+        unreachable!("Switched back to a terminated thread!");
     }
 }
 
@@ -340,12 +412,25 @@ pub fn tick() {
     sched.tick();
 }
 
+// The process can be terminated between a thread being attached to it and getting here. Leave it dead if so.
 pub fn register_thread(thread: Arc<Thread>) {
     let mut sched = SCHEDULER.lock();
-    thread.state.store(ThreadState::Runnable, Ordering::Release);
+    if thread
+        .state
+        .compare_exchange(
+            ThreadState::Created,
+            ThreadState::Runnable,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_err()
+    {
+        return;
+    }
 
     sched.threads.push_back(thread.clone());
     sched.runnable_threads.push_back(thread);
+    sched.update_preemption();
 }
 
 pub fn get_current_thread() -> Arc<Thread> {
@@ -373,33 +458,74 @@ pub fn wake_thread(p: &Arc<Thread>, tag: usize) {
     sched.wake(p, tag);
 }
 
-pub fn terminate_current_thread() {
+pub fn terminate_current_thread() -> ! {
     let mut sched = SCHEDULER.lock();
     sched.terminate_current_thread();
 }
 
-pub fn terminate_current_process() {
-    let mut sched = SCHEDULER.lock();
+// Records why the process exited, closes its handles and wakes anything waiting for it to exit.
+// Returns its threads, which the caller needs to terminate.
+fn teardown_process(
+    process: &Arc<Mutex<Process>>,
+    reason: ExitReason,
+    exit_code: i32,
+) -> LinkedList<ThreadProcessAdapter> {
+    // don't hold the process lock while terminating threads
+    let (threads, handle_table, exit_waiters) = {
+        let mut process = process.lock();
+
+        if process.exit_reason.is_none() {
+            process.exit_reason = Some(reason);
+            process.exit_code = exit_code;
+        }
 
-    // don't hold the process lock while calling terminate_current_thread
-    {
-        let current_thread = crate::per_cpu::get_current_thread();
-        let current_process = current_thread.process.clone();
-        let process = current_process.lock();
-
-        let mut cursor = process.threads.front();
-        while !cursor.is_null() {
-            let thread = cursor.get().unwrap();
-            if thread.id != current_thread.id {
-                sched.suspend(&cursor.clone_pointer().unwrap());
-            }
-            cursor.move_next();
+        (
+            process.threads.take(),
+            core::mem::replace(&mut process.handle_table, HandleTable::new()),
+            process.exit_waiter.take_waiters(usize::MAX),
+        )
+    };
+
+    // Closing handles and waking waiters can wake threads, so do it before taking the scheduler lock.
+    drop(handle_table);
+    for (thread, tag) in exit_waiters {
+        wake_thread(&thread, tag);
+    }
+
+    threads
+}
+
+pub fn terminate_current_process(reason: ExitReason, exit_code: i32) -> ! {
+    let current_thread = crate::per_cpu::get_current_thread();
+    let threads = teardown_process(&current_thread.process, reason, exit_code);
+
+    let mut sched = SCHEDULER.lock();
+    for thread in threads {
+        if thread.id != current_thread.id {
+            sched.terminate_thread(&thread);
         }
     }
+    drop(current_thread);
 
     sched.terminate_current_thread();
 }
 
+// Threads blocked in waits are stopped too, anything that later tries to wake them will skip them.
+// Only the boot CPU runs user threads (switch_thread checks this), so none of them can be running anywhere else right now.
+// Once APs schedule threads too, this needs to IPI whichever CPU is running one of them.
+pub fn terminate_process(process: &Arc<Mutex<Process>>, reason: ExitReason, exit_code: i32) {
+    if Arc::ptr_eq(&get_current_process(), process) {
+        terminate_current_process(reason, exit_code);
+    }
+
+    let threads = teardown_process(process, reason, exit_code);
+
+    let mut sched = SCHEDULER.lock();
+    for thread in threads {
+        sched.terminate_thread(&thread);
+    }
+}
+
 // see also: force_unlock_mutex
 extern "C" {
     fn setup_initial_thread_context(ctx: &ThreadContext, mutex: usize);
@@ -409,6 +535,7 @@ pub fn force_switch_to(thread: Arc<Thread>) {
     {
         thread.state.store(ThreadState::Runnable, Ordering::Release);
         crate::per_cpu::set_current_thread(thread.clone());
+        crate::timer::restart_timeslice(SCHEDULER.lock().has_multiple_runnable());
     }
 
     thread.process.lock().use_pages();
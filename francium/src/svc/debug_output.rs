@@ -1,17 +1,24 @@
-pub fn svc_debug_output(user_ptr: *const u8, len: usize) {
+use crate::user_ptr::copy_from_user;
+
+pub fn svc_debug_output(user_ptr: usize, len: usize) {
+    // Anything past the end of the buffer gets cut off.
     let mut temp_buffer: [u8; 1024] = [0; 1024];
-    unsafe {
-        core::ptr::copy_nonoverlapping(user_ptr, temp_buffer.as_mut_ptr(), len);
+    let len = core::cmp::min(len, temp_buffer.len());
+    if len == 0 || copy_from_user(user_ptr, &mut temp_buffer[0..len]).is_err() {
+        return;
     }
 
     // These are on seperate lines so a potential panic doesn't occur inside the print
     // (which locks the serial port, leading to a hang when panic tries to print).
-    let as_utf8 = core::str::from_utf8(&temp_buffer[0..len]).unwrap();
+    // Cutting the buffer off can split a character, so print whatever is valid.
+    let as_utf8 = match core::str::from_utf8(&temp_buffer[0..len]) {
+        Ok(as_utf8) => as_utf8,
+        Err(err) => match core::str::from_utf8(&temp_buffer[0..err.valid_up_to()]) {
+            Ok(as_utf8) => as_utf8,
+            Err(_) => return,
+        },
+    };
 
     // Strip a newline off the end, if it's present. Log will add one for us.
-    if &as_utf8[len - 1..len] == "\n" {
-        log::debug!("{}", &as_utf8[0..len - 1]);
-    } else {
-        log::debug!("{}", as_utf8);
-    }
+    log::debug!("{}", as_utf8.strip_suffix('\n').unwrap_or(as_utf8));
 }
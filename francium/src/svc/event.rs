@@ -4,6 +4,7 @@ use crate::platform::{INTERRUPT_CONTROLLER, INTERRUPT_DISTRIBUTOR};
 use crate::scheduler;
 use crate::waitable::{Waitable, Waiter};
 use alloc::sync::Arc;
+use common::handle::HandleRights;
 use common::os_error::{Module, Reason, ResultCode, RESULT_OK};
 use core::sync::atomic::AtomicU32;
 use core::sync::atomic::Ordering;
@@ -37,24 +38,31 @@ pub fn svc_create_event() -> (ResultCode, u32) {
     let proc_locked = scheduler::get_current_process();
     let mut process = proc_locked.lock();
 
-    let handle_value = process
+    match process
         .handle_table
-        .get_handle(HandleObject::Event(Arc::new(ev)));
-
-    (RESULT_OK, handle_value)
+        .get_handle(HandleObject::Event(Arc::new(ev)))
+    {
+        Ok(handle_value) => (RESULT_OK, handle_value),
+        Err(res) => (res, 0xffffffff),
+    }
 }
 
 pub fn svc_signal_event(h: u32) -> ResultCode {
     let proc = scheduler::get_current_process();
     let proc_locked = proc.lock();
 
-    if let HandleObject::Event(ev) = proc_locked.handle_table.get_object(h) {
-        drop(proc_locked);
+    match proc_locked
+        .handle_table
+        .get_object_checked(h, HandleRights::SIGNAL)
+    {
+        Ok(HandleObject::Event(ev)) => {
+            drop(proc_locked);
 
-        ev.w.signal_one(true);
-        RESULT_OK
-    } else {
-        ResultCode::new(Module::Kernel, Reason::InvalidHandle)
+            ev.w.signal_one(true);
+            RESULT_OK
+        }
+        Ok(_) => ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+        Err(res) => res,
     }
 }
 
@@ -62,17 +70,22 @@ pub fn svc_clear_event(h: u32) -> ResultCode {
     let proc_locked = scheduler::get_current_process();
     let process = proc_locked.lock();
 
-    if let HandleObject::Event(ev) = process.handle_table.get_object(h) {
-        ev.w.clear();
-
-        let interrupt_id = ev.interrupt.load(Ordering::Acquire);
-        if interrupt_id != 0 {
-            log::trace!("clearing interrupt event {}", interrupt_id);
-            INTERRUPT_CONTROLLER.lock().ack_interrupt(interrupt_id);
+    match process
+        .handle_table
+        .get_object_checked(h, HandleRights::SIGNAL)
+    {
+        Ok(HandleObject::Event(ev)) => {
+            ev.w.clear();
+
+            let interrupt_id = ev.interrupt.load(Ordering::Acquire);
+            if interrupt_id != 0 {
+                log::trace!("clearing interrupt event {}", interrupt_id);
+                INTERRUPT_CONTROLLER.lock().ack_interrupt(interrupt_id);
+            }
+            RESULT_OK
         }
-        RESULT_OK
-    } else {
-        ResultCode::new(Module::Kernel, Reason::InvalidHandle)
+        Ok(_) => ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+        Err(res) => res,
     }
 }
 
@@ -95,35 +108,53 @@ pub fn svc_bind_interrupt(h: u32, index: usize) -> ResultCode {
     let proc_locked = scheduler::get_current_process();
     let process = proc_locked.lock();
 
-    if let HandleObject::Event(ev) = process.handle_table.get_object(h) {
-        let mut lock = INTERRUPT_EVENT_TABLE.lock();
-        if let None = lock[index] {
-            ev.interrupt.store(index as u32, Ordering::Release);
-            lock[index] = Some(ev);
-            INTERRUPT_DISTRIBUTOR.lock().enable_interrupt(index as u32);
-
-            RESULT_OK
-        } else {
-            ResultCode::new(Module::Kernel, Reason::Unknown)
+    match process
+        .handle_table
+        .get_object_checked(h, HandleRights::WRITE)
+    {
+        Ok(HandleObject::Event(ev)) => {
+            let mut lock = INTERRUPT_EVENT_TABLE.lock();
+            match lock.get(index) {
+                Some(None) => {
+                    ev.interrupt.store(index as u32, Ordering::Release);
+                    lock[index] = Some(ev);
+                    INTERRUPT_DISTRIBUTOR.lock().enable_interrupt(index as u32);
+
+                    RESULT_OK
+                }
+                Some(Some(_)) => ResultCode::new(Module::Kernel, Reason::AlreadyExists),
+                None => ResultCode::new(Module::Kernel, Reason::InvalidArgument),
+            }
         }
-    } else {
-        ResultCode::new(Module::Kernel, Reason::InvalidHandle)
+        Ok(_) => ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+        Err(res) => res,
     }
 }
 
+// Only unbinds the interrupt if it's bound to this event.
 pub fn svc_unbind_interrupt(h: u32, index: usize) -> ResultCode {
     let proc_locked = scheduler::get_current_process();
     let process = proc_locked.lock();
 
-    if let HandleObject::Event(_ev) = process.handle_table.get_object(h) {
-        let mut lock = INTERRUPT_EVENT_TABLE.lock();
-        if let Some(_x) = &lock[index] {
-            lock[index] = None;
-            INTERRUPT_DISTRIBUTOR.lock().disable_interrupt(index as u32);
-        } else {
-            return ResultCode::new(Module::Kernel, Reason::Unknown);
+    match process
+        .handle_table
+        .get_object_checked(h, HandleRights::WRITE)
+    {
+        Ok(HandleObject::Event(ev)) => {
+            let mut lock = INTERRUPT_EVENT_TABLE.lock();
+            match lock.get(index) {
+                Some(Some(bound)) if Arc::ptr_eq(bound, &ev) => {
+                    lock[index] = None;
+                    INTERRUPT_DISTRIBUTOR.lock().disable_interrupt(index as u32);
+                    ev.interrupt.store(0, Ordering::Release);
+
+                    RESULT_OK
+                }
+                Some(_) => ResultCode::new(Module::Kernel, Reason::NotFound),
+                None => ResultCode::new(Module::Kernel, Reason::InvalidArgument),
+            }
         }
+        Ok(_) => ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+        Err(res) => res,
     }
-
-    ResultCode::new(Module::Kernel, Reason::InvalidHandle)
 }
@@ -1,7 +1,12 @@
 use crate::scheduler;
+use common::process::ExitReason;
 use tracing::{event, Level};
 
-pub fn svc_exit_process() {
-    event!(Level::TRACE, svc_name = "exit_process");
-    scheduler::terminate_current_process();
+pub fn svc_exit_process(exit_code: i32) {
+    event!(
+        Level::TRACE,
+        svc_name = "exit_process",
+        exit_code = exit_code
+    );
+    scheduler::terminate_current_process(ExitReason::Exited, exit_code);
 }
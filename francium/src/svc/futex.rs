@@ -1,62 +1,237 @@
 use tracing::{event, Level};
 
+use crate::process::Thread;
 use crate::scheduler;
-use crate::waitable::Waiter;
+use crate::timer;
+use crate::user_ptr::UserPtr;
+use crate::waitable::{Waiter, TIMED_OUT_TAG};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use common::constants::WAIT_INFINITE;
 use common::os_error::{Module, Reason, ResultCode, RESULT_OK};
-use core::sync::atomic::{AtomicU32, Ordering};
 use hashbrown::{hash_map::Entry, HashMap};
 use spin::Mutex;
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FutexKey {
+    // Keyed on the address space's page table, and the virtual address.
+    Private(usize, usize),
+    // Memory that might be mapped in more than one address space is keyed on physical address instead.
+    Shared(usize),
+}
+
 lazy_static! {
-    static ref FUTEX_TABLE: Mutex<HashMap<usize, Waiter>> = Mutex::new(HashMap::new());
+    static ref FUTEX_TABLE: Mutex<HashMap<FutexKey, Waiter>> = Mutex::new(HashMap::new());
+}
+
+fn get_futex_key(addr: usize) -> Result<FutexKey, ResultCode> {
+    if addr & 3 != 0 {
+        return Err(ResultCode::new(Module::Kernel, Reason::InvalidArgument));
+    }
+
+    let process_locked = scheduler::get_current_process();
+    let process = process_locked.lock();
+    let aspace = &process.address_space;
+    if !aspace.can_access_user(addr, 4, false) {
+        return Err(ResultCode::new(Module::Kernel, Reason::InvalidArgument));
+    }
+
+    let phys = match aspace.page_table.virt_to_phys(addr) {
+        Some(phys) => phys,
+        None => return Err(ResultCode::new(Module::Kernel, Reason::InvalidArgument)),
+    };
+
+    if aspace.is_shared(addr) {
+        Ok(FutexKey::Shared(phys.0))
+    } else {
+        Ok(FutexKey::Private(aspace.page_table_phys.0, addr))
+    }
+}
+
+// Only the futexes an operation touched can have become empty, so only those are checked.
+fn remove_if_empty(table: &mut HashMap<FutexKey, Waiter>, key: FutexKey) {
+    if table.get(&key).map_or(false, |waiter| waiter.is_empty()) {
+        table.remove(&key);
+    }
+}
+
+// The thread might have been requeued since it started waiting, so look up where it is now.
+fn futex_timed_out(thread: &Arc<Thread>) {
+    let mut table_lock = FUTEX_TABLE.lock();
+    let key = match *thread.futex_key.lock() {
+        Some(key) => key,
+        None => return,
+    };
+    let removed = table_lock
+        .get(&key)
+        .map_or(false, |waiter| waiter.remove_thread(thread.id));
+    if removed {
+        scheduler::wake_thread(thread, TIMED_OUT_TAG);
+        remove_if_empty(&mut table_lock, key);
+    }
 }
 
-pub fn svc_futex_wait(addr: usize, expected: u32, _timeout_ns: usize) -> ResultCode {
+pub fn svc_futex_wait(addr: usize, expected: u32, timeout_ns: u64) -> ResultCode {
     event!(
         Level::TRACE,
         svc_name = "futex_wait",
         addr = addr,
         expected = expected,
-        timeout = _timeout_ns
+        timeout = timeout_ns
     );
-    // TODO: uhhh
-    let futex_valid = unsafe { (*(addr as *mut AtomicU32)).load(Ordering::SeqCst) == expected };
 
-    if futex_valid {
-        {
-            let mut table_lock = FUTEX_TABLE.lock();
-            let waiter = match table_lock.entry(addr) {
-                Entry::Occupied(o) => o.into_mut(),
-                Entry::Vacant(v) => v.insert(Waiter::new()),
-            };
-            waiter.post_wait(0);
+    let key = match get_futex_key(addr) {
+        Ok(key) => key,
+        Err(res) => return res,
+    };
+
+    {
+        let mut table_lock = FUTEX_TABLE.lock();
+
+        // Check the value with the table locked, so a wake can't sneak in before we're on the wait list.
+        match UserPtr::<u32>::new(addr).read() {
+            Ok(value) if value == expected => {}
+            Ok(_) => return ResultCode::new(Module::Kernel, Reason::TryAgain),
+            Err(res) => return res,
         }
-        scheduler::suspend_current_thread();
 
-        RESULT_OK
+        let waiter = match table_lock.entry(key) {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => v.insert(Waiter::new()),
+        };
+        waiter.post_wait(0);
+        *scheduler::get_current_thread().futex_key.lock() = Some(key);
+    }
+
+    let timeout_timer = if timeout_ns != WAIT_INFINITE {
+        let thread = scheduler::get_current_thread();
+        Some(timer::register_timer(
+            timeout_ns,
+            Box::new(move || futex_timed_out(&thread)),
+        ))
+    } else {
+        None
+    };
+
+    let tag = scheduler::suspend_current_thread();
+
+    if let Some(timeout_timer) = timeout_timer {
+        timer::cancel_timer(timeout_timer);
+    }
+    *scheduler::get_current_thread().futex_key.lock() = None;
+
+    if tag == TIMED_OUT_TAG {
+        ResultCode::new(Module::Kernel, Reason::TimedOut)
     } else {
-        ResultCode::new(Module::Kernel, Reason::TryAgain)
+        RESULT_OK
+    }
+}
+
+fn futex_wake_impl(addr: usize, count: usize) -> Result<usize, ResultCode> {
+    let key = get_futex_key(addr)?;
+
+    let woken = {
+        let mut table_lock = FUTEX_TABLE.lock();
+        let woken = match table_lock.get(&key) {
+            Some(waiter) => waiter.signal_n(count),
+            None => 0,
+        };
+        remove_if_empty(&mut table_lock, key);
+        woken
+    };
+
+    if woken != 0 {
+        scheduler::tick();
     }
+
+    Ok(woken)
 }
 
 pub fn svc_futex_wake(addr: usize) -> ResultCode {
     event!(Level::TRACE, svc_name = "futex_wake", addr = addr);
 
-    let mut did_wake = false;
-    match FUTEX_TABLE.lock().get(&addr) {
-        Some(x) => {
-            x.signal_all();
-            did_wake = true;
-        }
-        None => {
-            // TODO: what do we do? currently nothing
-            //println!("No futex!");
-        }
+    match futex_wake_impl(addr, usize::MAX) {
+        Ok(_) => RESULT_OK,
+        Err(res) => res,
+    }
+}
+
+pub fn svc_futex_wake_count(addr: usize, count: usize) -> (ResultCode, usize) {
+    event!(
+        Level::TRACE,
+        svc_name = "futex_wake_count",
+        addr = addr,
+        count = count
+    );
+
+    match futex_wake_impl(addr, count) {
+        Ok(woken) => (RESULT_OK, woken),
+        Err(res) => (res, 0),
     }
+}
+
+// Wakes up to wake_count waiters, then moves up to requeue_count of the rest onto target_addr.
+// Returns how many threads were woken or requeued.
+pub fn svc_futex_requeue(
+    addr: usize,
+    wake_count: usize,
+    target_addr: usize,
+    requeue_count: usize,
+) -> (ResultCode, usize) {
+    event!(
+        Level::TRACE,
+        svc_name = "futex_requeue",
+        addr = addr,
+        wake_count = wake_count,
+        target_addr = target_addr,
+        requeue_count = requeue_count
+    );
+
+    let key = match get_futex_key(addr) {
+        Ok(key) => key,
+        Err(res) => return (res, 0),
+    };
+    let target_key = match get_futex_key(target_addr) {
+        Ok(key) => key,
+        Err(res) => return (res, 0),
+    };
+
+    let (woken, requeued) = {
+        let mut table_lock = FUTEX_TABLE.lock();
+
+        let (woken, to_requeue) = match table_lock.get(&key) {
+            Some(waiter) => {
+                let woken = waiter.signal_n(wake_count);
+                let to_requeue = if key != target_key {
+                    waiter.take_waiters(requeue_count)
+                } else {
+                    Default::default()
+                };
+                (woken, to_requeue)
+            }
+            None => (0, Default::default()),
+        };
+
+        let requeued = to_requeue.len();
+        if requeued != 0 {
+            for (thread, _) in to_requeue.iter() {
+                *thread.futex_key.lock() = Some(target_key);
+            }
+
+            let target = match table_lock.entry(target_key) {
+                Entry::Occupied(o) => o.into_mut(),
+                Entry::Vacant(v) => v.insert(Waiter::new()),
+            };
+            target.add_waiters(to_requeue);
+        }
+
+        remove_if_empty(&mut table_lock, key);
+        (woken, requeued)
+    };
 
-    if did_wake {
+    if woken != 0 {
         scheduler::tick();
     }
 
-    RESULT_OK
+    (RESULT_OK, woken + requeued)
 }
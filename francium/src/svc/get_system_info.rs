@@ -1,47 +1,46 @@
-use common::os_error::{ResultCode, RESULT_OK};
+use crate::user_ptr::UserPtr;
+use common::os_error::{Module, Reason, ResultCode, RESULT_OK};
 use common::system_info::*;
+use core::convert::TryFrom;
 
-pub fn svc_get_system_info(
-    ty: SystemInfoType,
-    _index: usize,
-    out_ptr: *mut SystemInfo,
-) -> ResultCode {
-    match ty {
+pub fn svc_get_system_info(ty: usize, _index: usize, out_ptr: UserPtr<SystemInfo>) -> ResultCode {
+    // Not every value is a valid SystemInfoType, so it can't come in as one.
+    let ty = match SystemInfoType::try_from(ty) {
+        Ok(ty) => ty,
+        Err(_) => return ResultCode::new(Module::Kernel, Reason::InvalidArgument),
+    };
+
+    let info = match ty {
         SystemInfoType::Platform => {
             #[cfg(feature = "platform_pc")]
             {
-                unsafe {
-                    *out_ptr = SystemInfo::Platform(Platform::Pc);
-                }
-                RESULT_OK
+                SystemInfo::Platform(Platform::Pc)
             }
 
             #[cfg(feature = "platform_virt")]
             {
-                unsafe {
-                    *out_ptr = SystemInfo::Platform(Platform::Virt);
-                }
-                RESULT_OK
+                SystemInfo::Platform(Platform::Virt)
             }
 
             #[cfg(feature = "platform_raspi3")]
             {
-                unsafe {
-                    *out_ptr = SystemInfo::Platform(Platform::Raspi3);
-                }
-                RESULT_OK
+                SystemInfo::Platform(Platform::Raspi3)
             }
 
             #[cfg(feature = "platform_raspi4")]
             {
-                unsafe {
-                    *out_ptr = SystemInfo::Platform(Platform::Raspi4);
-                }
-                RESULT_OK
+                SystemInfo::Platform(Platform::Raspi4)
             }
         }
-        _ => {
-            unimplemented!();
-        }
+        SystemInfoType::FirmwareTable => match crate::platform::get_firmware_table() {
+            Some(table) => SystemInfo::FirmwareTable(table),
+            None => return ResultCode::new(Module::Kernel, Reason::NotFound),
+        },
+        _ => return ResultCode::new(Module::Kernel, Reason::NotImplemented),
+    };
+
+    match out_ptr.write(info) {
+        Ok(()) => RESULT_OK,
+        Err(res) => res,
     }
 }
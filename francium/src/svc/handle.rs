@@ -1,14 +1,37 @@
 use tracing::{event, Level};
 
 use crate::scheduler;
-use common::os_error::ResultCode;
+use common::handle::HandleRights;
+use common::os_error::{ResultCode, RESULT_OK};
 
 pub fn svc_close_handle(handle: u32) -> ResultCode {
     event!(Level::TRACE, svc_name = "close_handle", handle = handle);
 
     // todo: proper thread locals, etc etc.
+    let p_ = scheduler::get_current_process();
+
+    // Closing can wake other threads, so let go of the process lock first.
+    let closed = p_.lock().handle_table.close(handle);
+    match closed {
+        Ok(_) => RESULT_OK,
+        Err(res) => res,
+    }
+}
+
+// Makes a new handle to the same object. The new handle can only have a subset of the original's rights.
+pub fn svc_duplicate_handle(handle: u32, rights: u32) -> (ResultCode, u32) {
+    event!(
+        Level::TRACE,
+        svc_name = "duplicate_handle",
+        handle = handle,
+        rights = rights
+    );
+
     let p_ = scheduler::get_current_process();
     let mut p = p_.lock();
 
-    p.handle_table.close(handle)
+    match p.handle_table.duplicate(handle, HandleRights(rights)) {
+        Ok(new_handle) => (RESULT_OK, new_handle),
+        Err(res) => (res, 0xffffffff),
+    }
 }
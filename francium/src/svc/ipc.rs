@@ -2,51 +2,133 @@ use tracing::{event, Level};
 
 use crate::handle;
 use crate::handle::HandleObject;
-use crate::mmu::phys_to_virt;
 use crate::process::Thread;
 use crate::scheduler;
+use crate::user_ptr::{copy_from_user, copy_from_user_in, copy_to_user, copy_to_user_in, UserPtr};
 use crate::waitable;
-use crate::waitable::{Waitable, Waiter};
-use alloc::collections::BTreeMap;
+use crate::waitable::{Waitable, Waiter, CLOSED_TAG, MAX_HANDLES};
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
+use common::handle::HandleRights;
 use common::ipc::*;
 use common::os_error::{Module, Reason, ResultCode, RESULT_OK};
 use common::Handle;
 use core::convert::TryInto;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use spin::Mutex;
 
 use smallvec::SmallVec;
 
+// How many notifications can be waiting on a session before ipc_notify fails.
+const MAX_PENDING_NOTIFICATIONS: usize = 16;
+// How many connections can be waiting on a port before connecting fails, unless the server changes it.
+const DEFAULT_PORT_BACKLOG: usize = 16;
+
 #[derive(Debug)]
 pub struct ServerSession {
     wait: Waiter,
     connect_wait: Waiter,
-    pub queue: Mutex<SmallVec<[(Arc<Thread>, usize); 1]>>,
+    // Requests are handled in the order they were made.
+    pub queue: Mutex<VecDeque<(Arc<Thread>, usize, u64)>>,
+    // Already copied out of the sender, header and all.
+    notifications: Mutex<VecDeque<(u64, Vec<u8>)>>,
+    // Requests and notifications are numbered as they arrive, so the two queues can be served in that order.
+    next_sequence: AtomicU64,
     client: Mutex<Weak<ClientSession>>,
     client_thread: Mutex<Option<(Arc<Thread>, usize)>>,
+    // Picked by the server when it accepts, and handed to it with every message from the client.
+    badge: AtomicU64,
+    handle_count: AtomicUsize,
+    // Set once the last handle to either end is closed. Only ever goes from false to true.
+    closed: AtomicBool,
+    client_closed: AtomicBool,
+    // Set if the server turned the connection down, instead of it going away.
+    refused: AtomicBool,
 }
 
 #[derive(Debug)]
 pub struct ClientSession {
     wait: Waiter,
     server: Arc<ServerSession>,
+    handle_count: AtomicUsize,
 }
 
 #[derive(Debug)]
 pub struct Port {
     wait: Waiter,
-    // todo: queue default length
-    pub queue: Mutex<SmallVec<[Arc<ServerSession>; 1]>>,
+    tag: u64,
+    // Connections are accepted in the order they were made.
+    pub queue: Mutex<VecDeque<Arc<ServerSession>>>,
+    // Connecting fails with TryAgain once this many are waiting to be accepted.
+    backlog: AtomicUsize,
+    // Only handles that can accept keep the port open, connect-only handles don't count.
+    accept_handle_count: AtomicUsize,
+    closed: AtomicBool,
+    // Handles that can only connect wait here instead, and are only woken when the port closes.
+    close_wait: Waiter,
 }
 
 impl Port {
-    fn new() -> Port {
+    fn new(tag: u64) -> Port {
         Port {
             wait: Waiter::new(),
-            queue: Mutex::new(SmallVec::new()),
+            tag: tag,
+            queue: Mutex::new(VecDeque::new()),
+            backlog: AtomicUsize::new(DEFAULT_PORT_BACKLOG),
+            accept_handle_count: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            close_wait: Waiter::new(),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    pub fn get_close_waiter(&self) -> &Waiter {
+        &self.close_wait
+    }
+
+    pub fn add_handle(&self, rights: HandleRights) {
+        if rights.contains(HandleRights::ACCEPT) {
+            self.accept_handle_count.fetch_add(1, Ordering::AcqRel);
         }
     }
+
+    pub fn remove_handle(&self, rights: HandleRights) {
+        if rights.contains(HandleRights::ACCEPT)
+            && self.accept_handle_count.fetch_sub(1, Ordering::AcqRel) == 1
+        {
+            self.close();
+        }
+    }
+
+    // Nobody can accept on the port any more. Fail any pending connections, and take it off the named port list.
+    fn close(&self) {
+        let pending: SmallVec<[Arc<ServerSession>; 1]> = {
+            let mut queue = self.queue.lock();
+            self.closed.store(true, Ordering::Release);
+            queue.drain(..).collect()
+        };
+
+        if self.tag != 0 {
+            let mut ports = PORT_LIST.lock();
+            let is_us = match ports.get(&self.tag) {
+                Some(port) => core::ptr::eq(Arc::as_ptr(port), self),
+                None => false,
+            };
+            if is_us {
+                ports.remove(&self.tag);
+            }
+        }
+
+        for server_session in pending {
+            server_session.close();
+        }
+        self.signal_all();
+        self.close_wait.signal_all();
+    }
 }
 
 impl Waitable for Port {
@@ -60,9 +142,67 @@ impl ServerSession {
         ServerSession {
             wait: Waiter::new(),
             connect_wait: Waiter::new(),
-            queue: Mutex::new(SmallVec::new()),
+            queue: Mutex::new(VecDeque::new()),
+            notifications: Mutex::new(VecDeque::new()),
+            next_sequence: AtomicU64::new(0),
             client: Mutex::new(Weak::new()),
             client_thread: Mutex::new(None),
+            badge: AtomicU64::new(0),
+            handle_count: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            client_closed: AtomicBool::new(false),
+            refused: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    pub fn is_client_closed(&self) -> bool {
+        self.client_closed.load(Ordering::Acquire)
+    }
+
+    pub fn has_notifications(&self) -> bool {
+        !self.notifications.lock().is_empty()
+    }
+
+    pub fn add_handle(&self) {
+        self.handle_count.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub fn remove_handle(&self) {
+        if self.handle_count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.close();
+        }
+    }
+
+    // The server end is gone. Anyone connecting or waiting on a request gets woken with SessionClosed.
+    fn close(&self) {
+        let pending: SmallVec<[(Arc<Thread>, usize); 1]> = {
+            let mut queue = self.queue.lock();
+            self.closed.store(true, Ordering::Release);
+
+            let mut pending: SmallVec<[(Arc<Thread>, usize); 1]> = queue
+                .drain(..)
+                .map(|(thread, buffer, _)| (thread, buffer))
+                .collect();
+            pending.extend(self.client_thread.lock().take());
+            pending
+        };
+        self.notifications.lock().clear();
+
+        self.connect_wait.signal_all();
+
+        let client = self.client.lock().upgrade();
+        if let Some(client) = client {
+            for (thread, _) in pending {
+                if client.wait.remove_thread(thread.id) {
+                    scheduler::wake_thread(&thread, CLOSED_TAG);
+                }
+            }
+            // Anything waiting on the client end sees it signalled.
+            client.signal_all();
         }
     }
 }
@@ -77,8 +217,29 @@ impl ClientSession {
         ClientSession {
             wait: Waiter::new(),
             server: server,
+            handle_count: AtomicUsize::new(0),
         }
     }
+
+    pub fn is_server_closed(&self) -> bool {
+        self.server.is_closed()
+    }
+
+    pub fn add_handle(&self) {
+        self.handle_count.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub fn remove_handle(&self) {
+        if self.handle_count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.close();
+        }
+    }
+
+    // The client end is gone. The server sees the session signalled, and ipc_receive fails with SessionClosed.
+    fn close(&self) {
+        self.server.client_closed.store(true, Ordering::Release);
+        self.server.signal_all();
+    }
 }
 impl Waitable for ClientSession {
     fn get_waiter(&self) -> &Waiter {
@@ -94,7 +255,7 @@ lazy_static! {
 pub fn svc_create_port(tag: u64) -> (ResultCode, u32) {
     event!(Level::TRACE, svc_name = "create_port", tag = tag);
 
-    let server_port = Port::new();
+    let server_port = Port::new(tag);
     let server_port_handle = Arc::new(server_port);
 
     // if not a private port
@@ -104,7 +265,10 @@ pub fn svc_create_port(tag: u64) -> (ResultCode, u32) {
         {
             let mut ports = PORT_LIST.lock();
             if ports.contains_key(&tag) {
-                panic!("Port already contains key!");
+                return (
+                    ResultCode::new(Module::Kernel, Reason::AlreadyExists),
+                    0xffffffff,
+                );
             }
 
             ports.insert(tag, server_port_handle.clone());
@@ -126,16 +290,25 @@ pub fn svc_create_port(tag: u64) -> (ResultCode, u32) {
         }
     }
 
-    let proc_locked = scheduler::get_current_process();
-    let mut process = proc_locked.lock();
+    let res = {
+        let proc_locked = scheduler::get_current_process();
+        let mut process = proc_locked.lock();
+        process
+            .handle_table
+            .get_handle(HandleObject::Port(server_port_handle.clone()))
+    };
 
-    let handle_value = process
-        .handle_table
-        .get_handle(HandleObject::Port(server_port_handle));
-    (RESULT_OK, handle_value)
+    match res {
+        Ok(handle_value) => (RESULT_OK, handle_value),
+        Err(res) => {
+            // Nobody will ever be able to accept on it, so give the name back and turn away anyone who already connected.
+            server_port_handle.close();
+            (res, 0xffffffff)
+        }
+    }
 }
 
-fn connect_to_port_impl(port: &Arc<Port>) -> u32 {
+fn connect_to_port_impl(port: &Arc<Port>) -> Result<u32, ResultCode> {
     let server_session = Arc::new(ServerSession::new());
     let client_session = Arc::new(ClientSession::new(server_session.clone()));
 
@@ -143,20 +316,40 @@ fn connect_to_port_impl(port: &Arc<Port>) -> u32 {
     *server_session.client.lock() = Arc::downgrade(&client_session);
 
     // create the session, and wait for it to be accepted by the server
+    {
+        let mut queue = port.queue.lock();
+        if port.closed.load(Ordering::Acquire) {
+            return Err(ResultCode::new(Module::Kernel, Reason::SessionClosed));
+        }
+        if queue.len() >= port.backlog.load(Ordering::Acquire) {
+            return Err(ResultCode::new(Module::Kernel, Reason::TryAgain));
+        }
+        queue.push_back(server_session.clone());
+    }
 
-    port.queue.lock().push(server_session.clone());
-    port.signal_one();
+    // Don't tick until we're waiting, so we can't miss the port being closed.
+    port.signal_one_without_tick();
     server_session.connect_wait.wait();
 
+    if server_session.refused.load(Ordering::Acquire) {
+        return Err(ResultCode::new(Module::Kernel, Reason::NotAllowed));
+    }
+    if server_session.is_closed() {
+        return Err(ResultCode::new(Module::Kernel, Reason::SessionClosed));
+    }
+
     // return session
-    {
-        let current_process = scheduler::get_current_process();
-        let mut process = current_process.lock();
-        let handle_value = process
-            .handle_table
-            .get_handle(HandleObject::ClientSession(client_session));
-        handle_value
+    let current_process = scheduler::get_current_process();
+    let handle_value = current_process
+        .lock()
+        .handle_table
+        .get_handle(HandleObject::ClientSession(client_session.clone()));
+
+    // Let the server know if we couldn't make a handle for it.
+    if handle_value.is_err() {
+        client_session.close();
     }
+    handle_value
 }
 
 pub fn svc_connect_to_port_handle(h: u32) -> (ResultCode, u32) {
@@ -166,40 +359,55 @@ pub fn svc_connect_to_port_handle(h: u32) -> (ResultCode, u32) {
         handle = h
     );
 
-    if let HandleObject::Port(port) = handle::get_handle(h) {
-        (RESULT_OK, connect_to_port_impl(&port))
-    } else {
-        (
+    match handle::get_handle(h, HandleRights::CONNECT) {
+        Ok(HandleObject::Port(port)) => match connect_to_port_impl(&port) {
+            Ok(handle_value) => (RESULT_OK, handle_value),
+            Err(res) => (res, 0xffffffff),
+        },
+        Ok(_) => (
             ResultCode::new(Module::Kernel, Reason::InvalidHandle),
             0xffffffff,
-        )
+        ),
+        Err(res) => (res, 0xffffffff),
     }
 }
 
 pub fn svc_connect_to_named_port(tag: u64) -> (ResultCode, u32) {
     event!(Level::TRACE, svc_name = "connect_to_named_port", tag = tag);
 
-    let port = {
+    // The port can be closed again before we get to run, so keep trying until it's there.
+    let port = loop {
         let ports = PORT_LIST.lock();
         if let Some(server_port) = ports.get(&tag) {
-            server_port.clone()
-        } else {
-            // make sure to drop the lock guard before suspending ourselves!
-            drop(ports);
+            break server_port.clone();
+        }
 
-            PORT_WAITERS
-                .lock()
-                .push((tag, scheduler::get_current_thread()));
-            scheduler::suspend_current_thread();
+        // make sure to drop the lock guard before suspending ourselves!
+        drop(ports);
 
-            // oops, try again
-            {
-                let ports = PORT_LIST.lock();
-                ports.get(&tag).unwrap().clone()
-            }
-        }
+        PORT_WAITERS
+            .lock()
+            .push((tag, scheduler::get_current_thread()));
+        scheduler::suspend_current_thread();
     };
-    (RESULT_OK, connect_to_port_impl(&port))
+    match connect_to_port_impl(&port) {
+        Ok(handle_value) => (RESULT_OK, handle_value),
+        Err(res) => (res, 0xffffffff),
+    }
+}
+
+// IPC buffers are checked when the syscall is made, so a bad one fails there instead of partway through a transfer.
+fn check_ipc_buffer(ipc_buffer_ptr: usize) -> Result<(), ResultCode> {
+    let process = scheduler::get_current_process();
+    let process = process.lock();
+    if process
+        .address_space
+        .can_access_user(ipc_buffer_ptr, IPC_BUFFER_SIZE, true)
+    {
+        Ok(())
+    } else {
+        Err(ResultCode::new(Module::Kernel, Reason::InvalidArgument))
+    }
 }
 
 // x0: ipc session
@@ -211,112 +419,303 @@ pub fn svc_ipc_request(session_handle: u32, ipc_buffer_ptr: usize) -> ResultCode
         ipc_buffer_ptr = ipc_buffer_ptr
     );
 
-    if let HandleObject::ClientSession(client_session) = handle::get_handle(session_handle) {
-        // signal, then wait for reply
-        let current_thread = scheduler::get_current_thread();
+    if let Err(res) = check_ipc_buffer(ipc_buffer_ptr) {
+        return res;
+    }
 
-        client_session
-            .server
-            .queue
-            .lock()
-            .push((current_thread, ipc_buffer_ptr));
-        client_session.server.signal_one();
-        client_session.wait();
+    match handle::get_handle(session_handle, HandleRights::WRITE) {
+        Ok(HandleObject::ClientSession(client_session)) => {
+            // signal, then wait for reply
+            let current_thread = scheduler::get_current_thread();
+            {
+                let mut queue = client_session.server.queue.lock();
+                if client_session.is_server_closed() {
+                    return ResultCode::new(Module::Kernel, Reason::SessionClosed);
+                }
+                let sequence = client_session
+                    .server
+                    .next_sequence
+                    .fetch_add(1, Ordering::AcqRel);
+                queue.push_back((current_thread, ipc_buffer_ptr, sequence));
+            }
 
-        RESULT_OK
-    } else {
-        // error
-        ResultCode::new(Module::Kernel, Reason::InvalidHandle)
+            // Don't tick until we're waiting, so we can't miss the server closing its end.
+            client_session.server.signal_one_without_tick();
+            if client_session.wait() == CLOSED_TAG {
+                ResultCode::new(Module::Kernel, Reason::SessionClosed)
+            } else {
+                RESULT_OK
+            }
+        }
+        Ok(_) => ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+        Err(res) => res,
+    }
+}
+
+// Sends a one-way message. It gets copied into the session straight away, so we never wait on the server.
+// Notifications can't carry handles, there's nowhere to keep them until the server picks the message up.
+pub fn svc_ipc_notify(session_handle: u32, ipc_buffer_ptr: usize) -> ResultCode {
+    event!(
+        Level::TRACE,
+        svc_name = "ipc_notify",
+        session_handle = session_handle,
+        ipc_buffer_ptr = ipc_buffer_ptr
+    );
+
+    let client_session = match handle::get_handle(session_handle, HandleRights::WRITE) {
+        Ok(HandleObject::ClientSession(client_session)) => client_session,
+        Ok(_) => return ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+        Err(res) => return res,
+    };
+
+    let mut packed_header = [0u8; IPC_HEADER_SIZE];
+    if let Err(res) = copy_from_user(ipc_buffer_ptr, &mut packed_header) {
+        return res;
+    }
+
+    let mut header = match IPCHeader::read_from(&packed_header) {
+        Some(header) if header.translate_count == 0 => header,
+        _ => return ResultCode::new(Module::Kernel, Reason::InvalidArgument),
+    };
+
+    let mut message = Vec::new();
+    message.resize(header.size, 0u8);
+    if let Err(res) = copy_from_user(ipc_buffer_ptr, &mut message) {
+        return res;
+    }
+
+    let server_session = &client_session.server;
+
+    // The server can tell it's not supposed to reply.
+    header.flags |= IPC_FLAG_NOTIFICATION;
+    IPCHeader::write_to(&mut message, &header);
+    let process_id = scheduler::get_current_process().lock().id;
+    IPCHeader::write_sender(
+        &mut message,
+        process_id,
+        server_session.badge.load(Ordering::Acquire),
+    );
+    {
+        let mut notifications = server_session.notifications.lock();
+        if client_session.is_server_closed() {
+            return ResultCode::new(Module::Kernel, Reason::SessionClosed);
+        }
+        if notifications.len() >= MAX_PENDING_NOTIFICATIONS {
+            return ResultCode::new(Module::Kernel, Reason::TryAgain);
+        }
+        let sequence = server_session.next_sequence.fetch_add(1, Ordering::AcqRel);
+        notifications.push_back((sequence, message));
     }
+
+    server_session.signal_one();
+    RESULT_OK
 }
 
-const IPC_BUFFER_LEN: usize = 128;
+fn get_transferable_object(
+    thread: &Arc<Thread>,
+    handle: u32,
+) -> Option<(HandleObject, HandleRights)> {
+    let process = thread.process.lock();
+    match process
+        .handle_table
+        .get_object_checked(handle, HandleRights::TRANSFER)
+    {
+        Ok(obj) => Some((obj, process.handle_table.get_rights(handle))),
+        Err(_) => None,
+    }
+}
 
+// Copies a message from the sender's IPC buffer to the receiver's, translating handles on the way.
+// Only as much of the buffer as the header says is in use gets copied.
+// The receiver is told which process sent it, and the session's badge.
 fn do_ipc_transfer(
     from_thread: &Arc<Thread>,
     to_thread: &Arc<Thread>,
     from_ptr: usize,
     to_ptr: usize,
+    badge: u64,
 ) {
-    let from_ipc_buffer_ptr = phys_to_virt(
-        from_thread
-            .process
-            .lock()
-            .address_space
-            .page_table
-            .virt_to_phys(from_ptr)
-            .unwrap(),
-    ) as *const u8;
-    let to_ipc_buffer_ptr = phys_to_virt(
-        to_thread
-            .process
-            .lock()
-            .address_space
-            .page_table
-            .virt_to_phys(to_ptr)
-            .unwrap(),
-    ) as *mut u8;
+    let mut packed_header = [0u8; IPC_HEADER_SIZE];
+    let header = match copy_from_user_in(
+        &from_thread.process.lock().address_space,
+        from_ptr,
+        &mut packed_header,
+    ) {
+        Ok(()) => IPCHeader::read_from(&packed_header),
+        Err(_) => None,
+    };
 
-    unsafe {
-        core::ptr::copy_nonoverlapping(from_ipc_buffer_ptr, to_ipc_buffer_ptr, 128);
-    }
+    let from_process_id = from_thread.process.lock().id;
+
+    // A malformed header gets passed on untouched, so the receiver fails to read it.
+    let header = match header {
+        Some(header) => header,
+        None => {
+            IPCHeader::write_sender(&mut packed_header, from_process_id, badge);
+            let _ = copy_to_user_in(
+                &mut to_thread.process.lock().address_space,
+                to_ptr,
+                &packed_header,
+            );
+            return;
+        }
+    };
 
-    let to_ipc_buffer =
-        unsafe { core::slice::from_raw_parts_mut(to_ipc_buffer_ptr as *mut u8, IPC_BUFFER_LEN) };
+    let mut ipc_buffer = Vec::new();
+    ipc_buffer.resize(header.used_length(), 0u8);
+    if copy_from_user_in(
+        &from_thread.process.lock().address_space,
+        from_ptr,
+        &mut ipc_buffer,
+    )
+    .is_err()
+    {
+        let _ = copy_to_user_in(
+            &mut to_thread.process.lock().address_space,
+            to_ptr,
+            &[0u8; IPC_HEADER_SIZE],
+        );
+        return;
+    }
 
-    let packed_header = u32::from_le_bytes(to_ipc_buffer[0..4].try_into().unwrap());
-    let header = IPCHeader::unpack(packed_header);
+    IPCHeader::write_sender(&mut ipc_buffer, from_process_id, badge);
 
     // Translate all translate parameters
     for i in 0..header.translate_count {
-        let off = header.size + i * 16;
-        let entry = TranslateEntry::read(to_ipc_buffer[off..off + 16].try_into().unwrap());
+        let off = header.size + i * TRANSLATE_ENTRY_SIZE;
+        let entry = TranslateEntry::read(
+            ipc_buffer[off..off + TRANSLATE_ENTRY_SIZE]
+                .try_into()
+                .unwrap(),
+        );
 
         let new_entry = match entry {
             TranslateEntry::CopyHandle(handle) => {
-                let obj = from_thread.process.lock().handle_table.get_object(handle.0);
-                let new_handle = to_thread.process.lock().handle_table.get_handle(obj);
+                // The receiver gets an invalid handle if the sender isn't allowed to transfer this one, or their handle table is full.
+                // Rights are carried over as-is.
+                let new_handle = match get_transferable_object(from_thread, handle.0) {
+                    Some((obj, rights)) => to_thread
+                        .process
+                        .lock()
+                        .handle_table
+                        .get_handle_with_rights(obj, rights)
+                        .unwrap_or(0xffffffff),
+                    None => 0xffffffff,
+                };
                 TranslateEntry::CopyHandle(Handle(new_handle))
             }
             TranslateEntry::MoveHandle(handle) => {
-                let obj = from_thread.process.lock().handle_table.get_object(handle.0);
-                let new_handle = to_thread.process.lock().handle_table.get_handle(obj);
-                assert!(from_thread.process.lock().handle_table.close(handle.0) == RESULT_OK);
+                // Only close the sender's handle if the move actually succeeded.
+                let new_handle = match get_transferable_object(from_thread, handle.0) {
+                    Some((obj, rights)) => to_thread
+                        .process
+                        .lock()
+                        .handle_table
+                        .get_handle_with_rights(obj, rights),
+                    None => Err(ResultCode::new(Module::Kernel, Reason::NotAllowed)),
+                };
+                let new_handle = match new_handle {
+                    Ok(new_handle) => {
+                        // Another thread in the sender might have closed it since we looked it up.
+                        let closed = {
+                            let mut process = from_thread.process.lock();
+                            process
+                                .handle_table
+                                .get_object_checked(handle.0, HandleRights::TRANSFER)
+                                .and_then(|_| process.handle_table.close(handle.0))
+                        };
+                        match closed {
+                            Ok(_) => new_handle,
+                            Err(_) => {
+                                // Dropped after the receiver's process lock is released.
+                                let _undone =
+                                    to_thread.process.lock().handle_table.close(new_handle);
+                                0xffffffff
+                            }
+                        }
+                    }
+                    Err(_) => 0xffffffff,
+                };
 
                 TranslateEntry::MoveHandle(Handle(new_handle))
             }
-            _ => {
-                unimplemented!("Can't translate {:?}", entry);
-            }
+            // Nothing we know how to translate, so the receiver gets nothing.
+            _ => TranslateEntry::None,
         };
-        TranslateEntry::write(&mut to_ipc_buffer[off..off + 16], new_entry);
+        TranslateEntry::write(&mut ipc_buffer[off..off + TRANSLATE_ENTRY_SIZE], new_entry);
     }
+
+    // Both buffers were checked when their syscalls were made, and memory is never unmapped, so this can't fail.
+    let _ = copy_to_user_in(
+        &mut to_thread.process.lock().address_space,
+        to_ptr,
+        &ipc_buffer,
+    );
 }
 
-const MAX_HANDLES: usize = 128;
 pub fn svc_ipc_receive(
-    handles_ptr: *const u32,
+    handles_ptr: UserPtr<u32>,
     handle_count: usize,
     ipc_buffer_ptr: usize,
+    timeout_ns: u64,
 ) -> (ResultCode, usize) {
     event!(
         Level::TRACE,
         svc_name = "ipc_receive",
-        handles_ptr = handles_ptr as usize,
+        handles_ptr = handles_ptr.address(),
         handle_count = handle_count,
-        ipc_buffer_ptr = ipc_buffer_ptr
+        ipc_buffer_ptr = ipc_buffer_ptr,
+        timeout_ns = timeout_ns
     );
 
-    let mut handles: [u32; MAX_HANDLES] = [0xffffffff; MAX_HANDLES];
+    if let Err(res) = check_ipc_buffer(ipc_buffer_ptr) {
+        return (res, 0);
+    }
 
-    unsafe {
-        core::ptr::copy_nonoverlapping(handles_ptr, &mut handles as *mut u32, handle_count);
+    if handle_count > MAX_HANDLES {
+        return (ResultCode::new(Module::Kernel, Reason::InvalidArgument), 0);
     }
 
-    let index = waitable::wait_handles(&handles[..handle_count]);
+    let mut handles: [u32; MAX_HANDLES] = [0xffffffff; MAX_HANDLES];
+    if let Err(res) = handles_ptr.read_slice(&mut handles[..handle_count]) {
+        return (res, 0);
+    }
 
-    if let HandleObject::ServerSession(server_session) = handle::get_handle(handles[index]) {
-        let (client_thread, client_buffer_ptr) = server_session.queue.lock().pop().unwrap();
+    let index = match waitable::wait_handles(&handles[..handle_count], timeout_ns) {
+        Ok(index) => index,
+        Err(res) => return (res, 0),
+    };
+
+    if let Ok(HandleObject::ServerSession(server_session)) =
+        handle::get_handle(handles[index], HandleRights::WAIT)
+    {
+        // Notifications are already copied out of the sender, and there's no reply to wait for.
+        // Whichever of the next notification and the next request was sent first goes first.
+        let notification = {
+            let next_request = server_session.queue.lock().front().map(|x| x.2);
+            let mut notifications = server_session.notifications.lock();
+            match (notifications.front(), next_request) {
+                (Some((sequence, _)), Some(next_request)) if *sequence > next_request => None,
+                _ => notifications.pop_front().map(|x| x.1),
+            }
+        };
+        if let Some(message) = notification {
+            let _ = copy_to_user(ipc_buffer_ptr, &message);
+            return (RESULT_OK, index);
+        }
+
+        let request = server_session.queue.lock().pop_front();
+        let (client_thread, client_buffer_ptr) = match request {
+            Some((client_thread, client_buffer_ptr, _)) => (client_thread, client_buffer_ptr),
+            // Any requests still queued get handled first, then the server finds out the client is gone.
+            None if server_session.is_client_closed() => {
+                return (
+                    ResultCode::new(Module::Kernel, Reason::SessionClosed),
+                    index,
+                )
+            }
+            None => return (ResultCode::new(Module::Kernel, Reason::TryAgain), index),
+        };
         let current_thread = scheduler::get_current_thread();
 
         // XX todo: figure out how to map from_ptr/to_ptr with respect to caches.
@@ -325,6 +724,7 @@ pub fn svc_ipc_receive(
             &current_thread,
             client_buffer_ptr,
             ipc_buffer_ptr,
+            server_session.badge.load(Ordering::Acquire),
         );
 
         *server_session.client_thread.lock() = Some((client_thread, client_buffer_ptr));
@@ -333,6 +733,49 @@ pub fn svc_ipc_receive(
     (RESULT_OK, index)
 }
 
+// Sends the reply and wakes the client, but doesn't reschedule. Returns whether the client was woken.
+fn ipc_reply_impl(session_handle: u32, ipc_buffer_ptr: usize) -> Result<bool, ResultCode> {
+    check_ipc_buffer(ipc_buffer_ptr)?;
+
+    match handle::get_handle(session_handle, HandleRights::WRITE) {
+        Ok(HandleObject::ServerSession(server_session)) => {
+            let current_thread = scheduler::get_current_thread();
+            let mut thread_lock = server_session.client_thread.lock();
+            let (client_thread, client_buffer_ptr) = match thread_lock.take() {
+                Some(request) => request,
+                // Nothing has been received on this session, or it was already replied to.
+                None => return Err(ResultCode::new(Module::Kernel, Reason::NotFound)),
+            };
+
+            // XX todo: figure out how to map from_ptr/to_ptr with respect to caches.
+            do_ipc_transfer(
+                &current_thread,
+                &client_thread,
+                ipc_buffer_ptr,
+                client_buffer_ptr,
+                0,
+            );
+
+            drop(thread_lock);
+
+            // Wake the thread that made the request, rather than whoever has been waiting on the session longest.
+            // If the client is gone, there's nobody to wake.
+            let client = server_session.client.lock().upgrade();
+            let did_wake = match client {
+                Some(client) if client.wait.remove_thread(client_thread.id) => {
+                    scheduler::wake_thread(&client_thread, 0);
+                    true
+                }
+                _ => false,
+            };
+
+            Ok(did_wake)
+        }
+        Ok(_) => Err(ResultCode::new(Module::Kernel, Reason::InvalidHandle)),
+        Err(res) => Err(res),
+    }
+}
+
 // x0: session handle
 pub fn svc_ipc_reply(session_handle: u32, ipc_buffer_ptr: usize) -> ResultCode {
     event!(
@@ -342,73 +785,142 @@ pub fn svc_ipc_reply(session_handle: u32, ipc_buffer_ptr: usize) -> ResultCode {
         ipc_buffer_ptr = ipc_buffer_ptr
     );
 
-    if let HandleObject::ServerSession(server_session) = handle::get_handle(session_handle) {
-        let current_thread = scheduler::get_current_thread();
-        let mut thread_lock = server_session.client_thread.lock();
-        let (client_thread, client_buffer_ptr) = thread_lock.as_ref().unwrap();
+    match ipc_reply_impl(session_handle, ipc_buffer_ptr) {
+        Ok(did_wake) => {
+            if did_wake {
+                scheduler::tick();
+            }
+            RESULT_OK
+        }
+        Err(res) => res,
+    }
+}
 
-        // XX todo: figure out how to map from_ptr/to_ptr with respect to caches.
-        do_ipc_transfer(
-            &current_thread,
-            &client_thread,
-            ipc_buffer_ptr,
-            *client_buffer_ptr,
-        );
+// Replies on one session, then waits for the next request on any of the handles.
+// The client we replied to gets to run once we block in receive, instead of after a separate reply syscall.
+// If the reply fails, nothing is received, and the index is REPLY_FAILED_INDEX.
+pub fn svc_ipc_reply_and_receive(
+    reply_session_handle: u32,
+    handles_ptr: UserPtr<u32>,
+    handle_count: usize,
+    ipc_buffer_ptr: usize,
+    timeout_ns: u64,
+) -> (ResultCode, usize) {
+    event!(
+        Level::TRACE,
+        svc_name = "ipc_reply_and_receive",
+        reply_session_handle = reply_session_handle,
+        handles_ptr = handles_ptr.address(),
+        handle_count = handle_count,
+        ipc_buffer_ptr = ipc_buffer_ptr,
+        timeout_ns = timeout_ns
+    );
+
+    if let Err(res) = ipc_reply_impl(reply_session_handle, ipc_buffer_ptr) {
+        return (res, REPLY_FAILED_INDEX);
+    }
 
-        *thread_lock = None;
+    svc_ipc_receive(handles_ptr, handle_count, ipc_buffer_ptr, timeout_ns)
+}
+
+// x0: port
+// x1: badge, given to the server with every message on the new session
+// x1 out: session handle
+pub fn svc_ipc_accept(port_handle: u32, badge: u64) -> (ResultCode, u32) {
+    event!(
+        Level::TRACE,
+        svc_name = "ipc_accept",
+        port_handle = port_handle,
+        badge = badge
+    );
 
-        let did_wake = {
-            server_session
-                .client
+    match handle::get_handle(port_handle, HandleRights::ACCEPT) {
+        Ok(HandleObject::Port(port)) => {
+            let server_session = match port.queue.lock().pop_front() {
+                Some(server_session) => server_session,
+                None => {
+                    return (
+                        ResultCode::new(Module::Kernel, Reason::TryAgain),
+                        0xffffffff,
+                    )
+                }
+            };
+            server_session.badge.store(badge, Ordering::Release);
+
+            let current_process = scheduler::get_current_process();
+            let handle_value = current_process
                 .lock()
-                .upgrade()
-                .unwrap()
-                .signal_one_without_tick()
-        };
+                .handle_table
+                .get_handle(HandleObject::ServerSession(server_session.clone()));
 
-        drop(thread_lock);
-        if did_wake {
-            scheduler::tick();
-        }
+            // The client gets SessionClosed if we couldn't make a handle for it.
+            if handle_value.is_err() {
+                server_session.close();
+            }
 
-        RESULT_OK
-    } else {
-        ResultCode::new(Module::Kernel, Reason::InvalidHandle)
+            // wake the client
+            server_session.connect_wait.signal_one(true);
+
+            match handle_value {
+                Ok(handle_value) => (RESULT_OK, handle_value),
+                Err(res) => (res, 0xffffffff),
+            }
+        }
+        Ok(_) => (
+            ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+            0xffffffff,
+        ),
+        Err(res) => (res, 0xffffffff),
     }
 }
 
-// x0: port
-// x1: session handle out
-pub fn svc_ipc_accept(port_handle: u32) -> (ResultCode, u32) {
+// Turns down the next connection waiting on the port. The client's connect fails with NotAllowed.
+pub fn svc_ipc_refuse(port_handle: u32) -> ResultCode {
     event!(
         Level::TRACE,
-        svc_name = "ipc_accept",
+        svc_name = "ipc_refuse",
         port_handle = port_handle
     );
 
-    if let HandleObject::Port(port) = handle::get_handle(port_handle) {
-        let server_session = port.queue.lock().pop().unwrap();
+    match handle::get_handle(port_handle, HandleRights::ACCEPT) {
+        Ok(HandleObject::Port(port)) => {
+            let server_session = match port.queue.lock().pop_front() {
+                Some(server_session) => server_session,
+                None => return ResultCode::new(Module::Kernel, Reason::TryAgain),
+            };
 
-        // wake the client
-        server_session.connect_wait.signal_one(true);
+            server_session.refused.store(true, Ordering::Release);
+            server_session.close();
+            RESULT_OK
+        }
+        Ok(_) => ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+        Err(res) => res,
+    }
+}
 
-        let current_process = scheduler::get_current_process();
-        let mut process = current_process.lock();
-        let handle_value = process
-            .handle_table
-            .get_handle(HandleObject::ServerSession(server_session));
-        (RESULT_OK, handle_value)
-    } else {
-        (
-            ResultCode::new(Module::Kernel, Reason::InvalidHandle),
-            0xffffffff,
-        )
+// Sets how many connections can wait on the port to be accepted. A backlog of 0 turns away everyone.
+// Connections already waiting aren't affected.
+pub fn svc_set_port_backlog(port_handle: u32, backlog: usize) -> ResultCode {
+    event!(
+        Level::TRACE,
+        svc_name = "set_port_backlog",
+        port_handle = port_handle,
+        backlog = backlog
+    );
+
+    match handle::get_handle(port_handle, HandleRights::ACCEPT) {
+        Ok(HandleObject::Port(port)) => {
+            port.backlog.store(backlog, Ordering::Release);
+            RESULT_OK
+        }
+        Ok(_) => ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+        Err(res) => res,
     }
 }
 
 pub fn svc_create_session(
-    server_session_out: *mut u32,
-    client_session_out: *mut u32,
+    server_session_out: UserPtr<u32>,
+    client_session_out: UserPtr<u32>,
 ) -> ResultCode {
     let proc_locked = scheduler::get_current_process();
     let mut process = proc_locked.lock();
@@ -419,16 +931,41 @@ pub fn svc_create_session(
     // TODO: ugh, i really wanted OnceCell here
     *server_session.client.lock() = Arc::downgrade(&client_session);
 
-    let server_session_handle = process
+    let server_session_handle = match process
         .handle_table
-        .get_handle(HandleObject::ServerSession(server_session));
-    let client_session_handle = process
+        .get_handle(HandleObject::ServerSession(server_session))
+    {
+        Ok(handle_value) => handle_value,
+        Err(res) => return res,
+    };
+    let client_session_handle = match process
         .handle_table
-        .get_handle(HandleObject::ClientSession(client_session));
+        .get_handle(HandleObject::ClientSession(client_session))
+    {
+        Ok(handle_value) => handle_value,
+        Err(res) => {
+            let closed = process.handle_table.close(server_session_handle);
+            drop(process);
+            drop(closed);
+            return res;
+        }
+    };
 
-    unsafe {
-        *server_session_out = server_session_handle;
-        *client_session_out = client_session_handle;
+    drop(process);
+
+    let written = server_session_out
+        .write(server_session_handle)
+        .and_then(|_| client_session_out.write(client_session_handle));
+
+    // Nobody would know about the handles, so don't leave them lying around.
+    if let Err(res) = written {
+        let mut process = proc_locked.lock();
+        let closed_server = process.handle_table.close(server_session_handle);
+        let closed_client = process.handle_table.close(client_session_handle);
+        drop(process);
+        drop(closed_server);
+        drop(closed_client);
+        return res;
     }
 
     RESULT_OK
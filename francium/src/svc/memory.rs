@@ -1,5 +1,7 @@
 use tracing::{event, Level};
 
+use crate::constants::{PAGE_SIZE, USER_ADDRESS_LIMIT};
+use crate::memory::AddressSpace;
 use crate::mmu::{MapType, PagePermission};
 use crate::scheduler;
 use common::os_error::{Module, Reason, ResultCode, RESULT_OK};
@@ -7,6 +9,31 @@ use francium_common::types::PhysAddr;
 
 use num_traits::cast::FromPrimitive;
 
+const MMAP_BASE: usize = 0x100000000;
+
+// Memory gets mapped after the highest region, as long as it fits below the user address limit.
+fn find_mmap_address(aspace: &AddressSpace, length: usize) -> Result<usize, ResultCode> {
+    let mut highest_mmap: usize = MMAP_BASE;
+    for region in &aspace.regions {
+        if region.address + region.size >= highest_mmap {
+            highest_mmap = region.address + region.size;
+        }
+    }
+
+    match highest_mmap.checked_add(length) {
+        Some(end) if end <= USER_ADDRESS_LIMIT => Ok(highest_mmap),
+        _ => Err(ResultCode::new(Module::Kernel, Reason::OutOfMemory)),
+    }
+}
+
+// Processes can't ask for kernel mappings.
+fn user_permission(permission: u64) -> Result<PagePermission, ResultCode> {
+    match PagePermission::from_bits(permission) {
+        Some(perm) if !perm.contains(PagePermission::KERNEL) => Ok(perm),
+        _ => Err(ResultCode::new(Module::Kernel, Reason::InvalidArgument)),
+    }
+}
+
 pub fn svc_map_memory(address: usize, length: usize, permission: u64) -> (ResultCode, usize) {
     event!(
         Level::TRACE,
@@ -16,27 +43,33 @@ pub fn svc_map_memory(address: usize, length: usize, permission: u64) -> (Result
         permission = permission
     );
 
-    let binding = scheduler::get_current_process();
-    let mut process_locked = binding.lock();
-    let aspace = &mut process_locked.address_space;
-
+    // Don't know how to deal with mmap hints yet.
     if address != 0 {
-        panic!("Don't know how to deal with mmap hints");
+        return (ResultCode::new(Module::Kernel, Reason::NotImplemented), 0);
     }
 
-    const MMAP_BASE: usize = 0x100000000;
-    let mut highest_mmap: usize = MMAP_BASE;
-    for region in &aspace.regions {
-        if region.address + region.size >= highest_mmap {
-            highest_mmap = region.address + region.size;
-        }
+    if length == 0 || length & (PAGE_SIZE - 1) != 0 {
+        return (ResultCode::new(Module::Kernel, Reason::InvalidArgument), 0);
     }
 
-    let page_permission: PagePermission = PagePermission::from_bits(permission).unwrap();
-    aspace.create(highest_mmap, length, page_permission);
-    //println!("{:x?}", aspace.regions);
+    let page_permission = match user_permission(permission) {
+        Ok(perm) => perm,
+        Err(res) => return (res, 0),
+    };
 
-    (RESULT_OK, highest_mmap)
+    let binding = scheduler::get_current_process();
+    let mut process_locked = binding.lock();
+    let aspace = &mut process_locked.address_space;
+
+    let mmap_address = match find_mmap_address(aspace, length) {
+        Ok(mmap_address) => mmap_address,
+        Err(res) => return (res, 0),
+    };
+
+    match aspace.try_create(mmap_address, length, page_permission) {
+        Ok(()) => (RESULT_OK, mmap_address),
+        Err(res) => (res, 0),
+    }
 }
 
 pub fn svc_map_device_memory(
@@ -55,32 +88,45 @@ pub fn svc_map_device_memory(
         permission = permission
     );
 
+    // Don't know how to deal with device memory mmap hints yet.
+    if virt_address != 0 {
+        return (ResultCode::new(Module::Kernel, Reason::NotImplemented), 0);
+    }
+
+    // Device memory doesn't always come in whole pages, so round up.
+    let length = match length.checked_add(PAGE_SIZE - 1) {
+        Some(length) if length >= PAGE_SIZE => length & !(PAGE_SIZE - 1),
+        _ => return (ResultCode::new(Module::Kernel, Reason::InvalidArgument), 0),
+    };
+
+    let page_permission = match user_permission(permission) {
+        Ok(perm) => perm,
+        Err(res) => return (res, 0),
+    };
+
+    let map_type = match MapType::from_usize(map_type) {
+        Some(map_type) => map_type,
+        None => return (ResultCode::new(Module::Kernel, Reason::InvalidArgument), 0),
+    };
+
     let binding = scheduler::get_current_process();
     let mut process_locked = binding.lock();
     let aspace = &mut process_locked.address_space;
 
-    if virt_address != 0 {
-        panic!("Don't know how to deal with device memory mmap hints");
-    }
+    let mmap_address = match find_mmap_address(aspace, length) {
+        Ok(mmap_address) => mmap_address,
+        Err(res) => return (res, 0),
+    };
 
-    const MMAP_BASE: usize = 0x100000000;
-    let mut highest_mmap: usize = MMAP_BASE;
-    for region in &aspace.regions {
-        if region.address + region.size >= highest_mmap {
-            highest_mmap = region.address + region.size;
-        }
-    }
-
-    let page_permission: PagePermission = PagePermission::from_bits(permission).unwrap();
     aspace.alias(
         phys_address,
-        highest_mmap,
+        mmap_address,
         length,
-        MapType::from_usize(map_type).unwrap(),
+        map_type,
         page_permission,
     );
 
-    (RESULT_OK, highest_mmap)
+    (RESULT_OK, mmap_address)
 }
 
 pub fn svc_query_physical_address(virt_address: usize) -> (ResultCode, usize) {
@@ -92,6 +138,11 @@ pub fn svc_query_physical_address(virt_address: usize) -> (ResultCode, usize) {
 
     let proc = scheduler::get_current_process();
     let locked = proc.lock();
+    // Kernel addresses are mapped too, but they're none of the process's business.
+    if !locked.address_space.can_access_user(virt_address, 1, false) {
+        return (ResultCode::new(Module::Kernel, Reason::NotFound), 0);
+    }
+
     if let Some(phys) = locked.address_space.page_table.virt_to_phys(virt_address) {
         (RESULT_OK, phys.0)
     } else {
@@ -1,7 +1,7 @@
 mod debug_output;
 pub mod event;
 mod exit_process;
-mod futex;
+pub mod futex;
 mod get_system_info;
 mod get_system_tick;
 mod handle;
@@ -10,11 +10,12 @@ mod memory;
 mod process;
 mod svc_break;
 mod thread;
+pub mod timer;
 mod wait;
 
 pub use debug_output::svc_debug_output;
 pub use exit_process::svc_exit_process;
-pub use handle::svc_close_handle;
+pub use handle::{svc_close_handle, svc_duplicate_handle};
 pub use ipc::svc_connect_to_named_port;
 pub use ipc::svc_connect_to_port_handle;
 pub use ipc::svc_create_port;
@@ -22,22 +23,34 @@ pub use svc_break::svc_break;
 
 pub use ipc::svc_create_session;
 pub use ipc::svc_ipc_accept;
+pub use ipc::svc_ipc_notify;
 pub use ipc::svc_ipc_receive;
+pub use ipc::svc_ipc_refuse;
 pub use ipc::svc_ipc_reply;
+pub use ipc::svc_ipc_reply_and_receive;
 pub use ipc::svc_ipc_request;
+pub use ipc::svc_set_port_backlog;
 
 pub use memory::svc_map_device_memory;
 pub use memory::svc_map_memory;
 pub use memory::svc_query_physical_address;
 
+pub use process::svc_create_process;
 pub use process::svc_create_thread;
+pub use process::svc_get_process_exit_info;
 pub use process::svc_get_process_id;
 pub use process::svc_get_thread_id;
+pub use process::svc_map_process_memory;
+pub use process::svc_start_process;
+pub use process::svc_terminate_process;
+pub use process::svc_write_process_memory;
 
 pub use thread::svc_sleep_ns;
 
+pub use futex::svc_futex_requeue;
 pub use futex::svc_futex_wait;
 pub use futex::svc_futex_wake;
+pub use futex::svc_futex_wake_count;
 
 pub use get_system_info::svc_get_system_info;
 
@@ -49,5 +62,10 @@ pub use event::svc_create_event;
 pub use event::svc_signal_event;
 pub use event::svc_unbind_interrupt;
 
+pub use timer::svc_cancel_timer;
+pub use timer::svc_create_timer;
+pub use timer::svc_set_timer;
+
+pub use wait::svc_cancel_wait;
 pub use wait::svc_wait_many;
 pub use wait::svc_wait_one;
@@ -1,9 +1,22 @@
 use tracing::{event, Level};
 
+use crate::arch::cache::clear_cache_for_address;
+use crate::constants::{PAGE_SIZE, USER_ADDRESS_LIMIT};
+use crate::handle::HandleObject;
 use crate::init;
-use crate::process::Thread;
+use crate::memory::{AddressSpace, KERNEL_ADDRESS_SPACE};
+use crate::mmu::{phys_to_virt, PagePermission};
+use crate::process::{Process, Thread};
 use crate::scheduler;
-use common::os_error::ResultCode;
+use crate::user_ptr::copy_from_user;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use common::handle::HandleRights;
+use common::os_error::{Module, Reason, ResultCode, RESULT_OK};
+use common::process::ExitReason;
+use spin::Mutex;
+
+const MAX_PROCESS_NAME_LEN: usize = 64;
 
 pub fn svc_get_process_id() -> usize {
     event!(Level::TRACE, svc_name = "get_process_id");
@@ -36,5 +49,250 @@ pub fn svc_create_thread(entry_point: usize, stack_top: usize) -> (ResultCode, u
     (ResultCode(0), tid as u32)
 }
 
-// svc_create_process
-// svc_map_process_memory
+fn get_process_handle(
+    handle: u32,
+    rights: HandleRights,
+) -> Result<Arc<Mutex<Process>>, ResultCode> {
+    match crate::handle::get_handle(handle, rights)? {
+        HandleObject::Process(process) => Ok(process),
+        _ => Err(ResultCode::new(Module::Kernel, Reason::InvalidHandle)),
+    }
+}
+
+fn is_user_range(address: usize, length: usize) -> bool {
+    match address.checked_add(length) {
+        Some(end) => end <= USER_ADDRESS_LIMIT,
+        None => false,
+    }
+}
+
+// Creates an empty process, with nothing mapped and no threads.
+pub fn svc_create_process(name_ptr: usize, name_len: usize) -> (ResultCode, u32) {
+    event!(
+        Level::TRACE,
+        svc_name = "create_process",
+        name_len = name_len
+    );
+
+    if name_len > MAX_PROCESS_NAME_LEN {
+        return (ResultCode::new(Module::Kernel, Reason::InvalidArgument), 0);
+    }
+
+    let mut name_buffer: [u8; MAX_PROCESS_NAME_LEN] = [0; MAX_PROCESS_NAME_LEN];
+    if let Err(res) = copy_from_user(name_ptr, &mut name_buffer[0..name_len]) {
+        return (res, 0);
+    }
+    let name = match core::str::from_utf8(&name_buffer[0..name_len]) {
+        Ok(name) => name,
+        Err(_) => return (ResultCode::new(Module::Kernel, Reason::InvalidArgument), 0),
+    };
+
+    let aspace = {
+        let page_table_root = &KERNEL_ADDRESS_SPACE.read().page_table;
+        match AddressSpace::try_new(page_table_root.user_process()) {
+            Ok(aspace) => aspace,
+            Err(res) => return (res, 0),
+        }
+    };
+    let new_process = Arc::new(Mutex::new(Process::new(name, aspace)));
+
+    let proc_locked = scheduler::get_current_process();
+    let mut process = proc_locked.lock();
+    match process
+        .handle_table
+        .get_handle(HandleObject::Process(new_process))
+    {
+        Ok(handle_value) => (RESULT_OK, handle_value),
+        Err(res) => (res, 0xffffffff),
+    }
+}
+
+// Maps fresh, zeroed memory into another process.
+pub fn svc_map_process_memory(
+    process_handle: u32,
+    address: usize,
+    length: usize,
+    permission: u64,
+) -> ResultCode {
+    event!(
+        Level::TRACE,
+        svc_name = "map_process_memory",
+        process_handle = process_handle,
+        address = address,
+        length = length,
+        permission = permission
+    );
+
+    let target = match get_process_handle(process_handle, HandleRights::MAP) {
+        Ok(target) => target,
+        Err(res) => return res,
+    };
+
+    if address & (PAGE_SIZE - 1) != 0
+        || length & (PAGE_SIZE - 1) != 0
+        || length == 0
+        || !is_user_range(address, length)
+    {
+        return ResultCode::new(Module::Kernel, Reason::InvalidArgument);
+    }
+
+    let page_permission = match PagePermission::from_bits(permission) {
+        Some(perm) if !perm.contains(PagePermission::KERNEL) => perm,
+        _ => return ResultCode::new(Module::Kernel, Reason::InvalidArgument),
+    };
+
+    let mut target_locked = target.lock();
+    let aspace = &mut target_locked.address_space;
+
+    if let Err(res) = aspace.try_create(address, length, page_permission) {
+        return res;
+    }
+
+    // Pages come straight off the free list, don't leak whatever was in them before.
+    for page in (address..address + length).step_by(PAGE_SIZE) {
+        let phys = aspace.page_table.virt_to_phys(page).unwrap();
+        unsafe {
+            core::ptr::write_bytes(phys_to_virt(phys) as *mut u8, 0, PAGE_SIZE);
+        }
+    }
+
+    RESULT_OK
+}
+
+// Copies from the current process into memory already mapped in another process.
+pub fn svc_write_process_memory(
+    process_handle: u32,
+    address: usize,
+    src: usize,
+    length: usize,
+) -> ResultCode {
+    event!(
+        Level::TRACE,
+        svc_name = "write_process_memory",
+        process_handle = process_handle,
+        address = address,
+        length = length
+    );
+
+    let target = match get_process_handle(process_handle, HandleRights::WRITE) {
+        Ok(target) => target,
+        Err(res) => return res,
+    };
+
+    if !is_user_range(address, length) || !is_user_range(src, length) {
+        return ResultCode::new(Module::Kernel, Reason::InvalidArgument);
+    }
+
+    // The target isn't the active address space, so go through the physmap a page at a time.
+    // Each page is staged in a kernel buffer first, so we never hold both processes' locks at once.
+    let mut staging = Vec::new();
+    staging.resize(core::cmp::min(length, PAGE_SIZE), 0u8);
+
+    let mut offset = 0;
+    while offset < length {
+        let dest = address + offset;
+        let chunk_len = core::cmp::min(PAGE_SIZE - (dest & (PAGE_SIZE - 1)), length - offset);
+        let chunk = &mut staging[..chunk_len];
+
+        if let Err(res) = copy_from_user(src + offset, chunk) {
+            return res;
+        }
+
+        let target_locked = target.lock();
+        let dest_virt = match target_locked.address_space.page_table.virt_to_phys(dest) {
+            Some(phys) => phys_to_virt(phys),
+            None => return ResultCode::new(Module::Kernel, Reason::InvalidArgument),
+        };
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(chunk.as_ptr(), dest_virt as *mut u8, chunk_len);
+
+            // TODO: proper cache management
+            for addr in ((dest_virt & !63)..(dest_virt + chunk_len)).step_by(64) {
+                clear_cache_for_address(addr);
+            }
+        }
+
+        offset += chunk_len;
+    }
+
+    RESULT_OK
+}
+
+// Creates the first thread of a process created with svc_create_process, and starts it running.
+pub fn svc_start_process(process_handle: u32, entry_point: usize, stack_top: usize) -> ResultCode {
+    event!(
+        Level::TRACE,
+        svc_name = "start_process",
+        process_handle = process_handle,
+        entry_point = entry_point,
+        stack_top = stack_top
+    );
+
+    let target = match get_process_handle(process_handle, HandleRights::WRITE) {
+        Ok(target) => target,
+        Err(res) => return res,
+    };
+
+    if entry_point >= USER_ADDRESS_LIMIT || stack_top > USER_ADDRESS_LIMIT {
+        return ResultCode::new(Module::Kernel, Reason::InvalidArgument);
+    }
+
+    let new_thread = Thread::new_unattached(target.clone());
+    init::setup_thread_context(&new_thread, entry_point, stack_top, false);
+
+    // Check and attach under the same lock, so two starts can't both get in, and terminate_process sees the thread.
+    {
+        let mut target_locked = target.lock();
+        if !target_locked.threads.is_empty() || target_locked.exit_reason.is_some() {
+            return ResultCode::new(Module::Kernel, Reason::NotAllowed);
+        }
+        target_locked.threads.push_back(new_thread.clone());
+    }
+
+    scheduler::register_thread(new_thread);
+
+    RESULT_OK
+}
+
+// Returns the exit reason in the top 32 bits, and the exit code in the bottom 32 bits.
+pub fn svc_get_process_exit_info(process_handle: u32) -> (ResultCode, usize) {
+    event!(
+        Level::TRACE,
+        svc_name = "get_process_exit_info",
+        process_handle = process_handle
+    );
+
+    let target = match get_process_handle(process_handle, HandleRights::READ) {
+        Ok(target) => target,
+        Err(res) => return (res, 0),
+    };
+
+    let target_locked = target.lock();
+    match target_locked.exit_reason {
+        Some(reason) => {
+            let reason: u32 = reason.into();
+            let info = ((reason as usize) << 32) | (target_locked.exit_code as u32 as usize);
+            (RESULT_OK, info)
+        }
+        None => (ResultCode::new(Module::Kernel, Reason::TryAgain), 0),
+    }
+}
+
+pub fn svc_terminate_process(process_handle: u32) -> ResultCode {
+    event!(
+        Level::TRACE,
+        svc_name = "terminate_process",
+        process_handle = process_handle
+    );
+
+    let target = match get_process_handle(process_handle, HandleRights::WRITE) {
+        Ok(target) => target,
+        Err(res) => return res,
+    };
+
+    scheduler::terminate_process(&target, ExitReason::Terminated, 0);
+    scheduler::tick();
+
+    RESULT_OK
+}
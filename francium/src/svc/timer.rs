@@ -0,0 +1,167 @@
+use tracing::{event, Level};
+
+use crate::handle::HandleObject;
+use crate::scheduler;
+use crate::timer::{self, TimerId};
+use crate::waitable::{Waitable, Waiter};
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use common::handle::HandleRights;
+use common::os_error::{Module, Reason, ResultCode, RESULT_OK};
+use spin::Mutex;
+
+#[derive(Debug)]
+struct TimerState {
+    pending: Option<TimerId>,
+    period_ns: u64,
+    // Bumped every time we're armed, so a callback that lost a race with set/cancel knows to do nothing.
+    generation: u64,
+}
+
+// A timer that signals its waiter when it expires, so it can be waited on alongside other handles.
+#[derive(Debug)]
+pub struct TimerObject {
+    w: Waiter,
+    state: Mutex<TimerState>,
+}
+
+impl TimerObject {
+    fn new() -> TimerObject {
+        TimerObject {
+            w: Waiter::new(),
+            state: Mutex::new(TimerState {
+                pending: None,
+                period_ns: 0,
+                generation: 0,
+            }),
+        }
+    }
+
+    fn arm(this: &Arc<TimerObject>, state: &mut TimerState, deadline: u64) {
+        state.generation += 1;
+        let generation = state.generation;
+        let weak = Arc::downgrade(this);
+        state.pending = Some(timer::register_timer_at(
+            deadline,
+            Box::new(move || TimerObject::expired(weak, generation, deadline)),
+        ));
+    }
+
+    fn expired(weak: Weak<TimerObject>, generation: u64, deadline: u64) {
+        // The handle might have been closed since we were armed.
+        let this = match weak.upgrade() {
+            Some(this) => this,
+            None => return,
+        };
+
+        {
+            let mut state = this.state.lock();
+            if state.pending.is_none() || state.generation != generation {
+                return;
+            }
+
+            state.pending = None;
+            if state.period_ns != 0 {
+                // Re-arm from the old deadline rather than from now, so periodic timers don't drift.
+                // Periods we were too late for are skipped, rather than all firing back to back.
+                let period_ns = state.period_ns;
+                let now = timer::get_counter_ns();
+                let mut next_deadline = deadline.saturating_add(period_ns);
+                if next_deadline <= now {
+                    let missed = (now - next_deadline) / period_ns + 1;
+                    next_deadline = next_deadline.saturating_add(missed.saturating_mul(period_ns));
+                }
+                TimerObject::arm(&this, &mut state, next_deadline);
+            }
+        }
+
+        // timer::tick runs the scheduler once all the callbacks are done.
+        this.w.signal_one(false);
+    }
+
+    fn cancel(&self) {
+        let mut state = self.state.lock();
+        if let Some(id) = state.pending.take() {
+            timer::cancel_timer(id);
+        }
+        state.generation += 1;
+        state.period_ns = 0;
+        self.w.clear();
+    }
+}
+
+impl Drop for TimerObject {
+    fn drop(&mut self) {
+        if let Some(id) = self.state.get_mut().pending.take() {
+            timer::cancel_timer(id);
+        }
+    }
+}
+
+impl Waitable for TimerObject {
+    fn get_waiter(&self) -> &Waiter {
+        &self.w
+    }
+}
+
+pub fn svc_create_timer() -> (ResultCode, u32) {
+    event!(Level::TRACE, svc_name = "create_timer");
+
+    let proc_locked = scheduler::get_current_process();
+    let mut process = proc_locked.lock();
+
+    match process
+        .handle_table
+        .get_handle(HandleObject::Timer(Arc::new(TimerObject::new())))
+    {
+        Ok(handle_value) => (RESULT_OK, handle_value),
+        Err(res) => (res, 0xffffffff),
+    }
+}
+
+// Anything shorter would have the timer interrupt firing constantly.
+const MIN_TIMER_PERIOD_NS: u64 = 100_000;
+
+// Arms the timer to fire after initial_ns, and then every period_ns. A period of 0 makes it one-shot.
+pub fn svc_set_timer(h: u32, initial_ns: u64, period_ns: u64) -> ResultCode {
+    event!(
+        Level::TRACE,
+        svc_name = "set_timer",
+        handle = h,
+        initial_ns = initial_ns,
+        period_ns = period_ns
+    );
+
+    if period_ns != 0 && period_ns < MIN_TIMER_PERIOD_NS {
+        return ResultCode::new(Module::Kernel, Reason::InvalidArgument);
+    }
+
+    match crate::handle::get_handle(h, HandleRights::WRITE) {
+        Ok(HandleObject::Timer(timer_object)) => {
+            // Anything left over from the last time it was armed shouldn't count.
+            timer_object.cancel();
+
+            let deadline = timer::get_counter_ns().saturating_add(initial_ns);
+            let mut state = timer_object.state.lock();
+            state.period_ns = period_ns;
+            TimerObject::arm(&timer_object, &mut state, deadline);
+
+            RESULT_OK
+        }
+        Ok(_) => ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+        Err(res) => res,
+    }
+}
+
+pub fn svc_cancel_timer(h: u32) -> ResultCode {
+    event!(Level::TRACE, svc_name = "cancel_timer", handle = h);
+
+    match crate::handle::get_handle(h, HandleRights::WRITE) {
+        Ok(HandleObject::Timer(timer_object)) => {
+            timer_object.cancel();
+            RESULT_OK
+        }
+        Ok(_) => ResultCode::new(Module::Kernel, Reason::InvalidHandle),
+        Err(res) => res,
+    }
+}
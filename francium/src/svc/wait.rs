@@ -1,29 +1,80 @@
+use crate::scheduler;
+use crate::user_ptr::UserPtr;
 use crate::waitable;
-use common::os_error::{ResultCode, RESULT_OK};
+use crate::waitable::MAX_HANDLES;
+use common::os_error::{Module, Reason, ResultCode, RESULT_OK};
 use tracing::{event, Level};
 
-pub fn svc_wait_one(handle: u32) -> ResultCode {
-    event!(Level::TRACE, svc_name = "wait_one", handle = handle);
-    waitable::wait_handles(&[handle]);
-    RESULT_OK
+pub fn svc_wait_one(handle: u32, timeout_ns: u64) -> ResultCode {
+    event!(
+        Level::TRACE,
+        svc_name = "wait_one",
+        handle = handle,
+        timeout_ns = timeout_ns
+    );
+
+    match waitable::wait_handles(&[handle], timeout_ns) {
+        Ok(_) => RESULT_OK,
+        Err(res) => res,
+    }
 }
 
-const MAX_HANDLES: usize = 128;
-pub fn svc_wait_many(handles_ptr: *const u32, handle_count: usize) -> (ResultCode, usize) {
+pub fn svc_wait_many(
+    handles_ptr: UserPtr<u32>,
+    handle_count: usize,
+    timeout_ns: u64,
+) -> (ResultCode, usize) {
     event!(
         Level::TRACE,
         svc_name = "svc_wait_many",
-        handles_ptr = handles_ptr as usize,
-        handle_count = handle_count
+        handles_ptr = handles_ptr.address(),
+        handle_count = handle_count,
+        timeout_ns = timeout_ns
     );
 
+    if handle_count > MAX_HANDLES {
+        return (ResultCode::new(Module::Kernel, Reason::InvalidArgument), 0);
+    }
+
     let mut handles: [u32; MAX_HANDLES] = [0xffffffff; MAX_HANDLES];
+    if let Err(res) = handles_ptr.read_slice(&mut handles[..handle_count]) {
+        return (res, 0);
+    }
 
-    unsafe {
-        core::ptr::copy_nonoverlapping(handles_ptr, &mut handles as *mut u32, handle_count);
+    match waitable::wait_handles(&handles[..handle_count], timeout_ns) {
+        Ok(index) => (RESULT_OK, index),
+        Err(res) => (res, 0),
     }
+}
 
-    let index = waitable::wait_handles(&handles[..handle_count]);
+// Cancels a wait on another thread in the same process.
+pub fn svc_cancel_wait(thread_id: usize) -> ResultCode {
+    event!(
+        Level::TRACE,
+        svc_name = "cancel_wait",
+        thread_id = thread_id
+    );
+
+    let thread = {
+        let process_locked = scheduler::get_current_process();
+        let process = process_locked.lock();
+        let mut cursor = process.threads.front();
+        let mut found = None;
+        while let Some(thread) = cursor.get() {
+            if thread.id == thread_id {
+                found = cursor.clone_pointer();
+                break;
+            }
+            cursor.move_next();
+        }
+        found
+    };
 
-    (RESULT_OK, index)
+    match thread {
+        Some(thread) => {
+            waitable::cancel_wait(&thread);
+            RESULT_OK
+        }
+        None => ResultCode::new(Module::Kernel, Reason::NotFound),
+    }
 }
@@ -2,45 +2,168 @@ use crate::drivers::Timer;
 use crate::platform::DEFAULT_TIMER;
 use crate::scheduler;
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
 use spin::Mutex;
 
-struct TimerEntry {
+// How long a thread gets to run before being preempted, if anything else is runnable.
+const TIMESLICE_NS: u64 = 10_000_000;
+
+// Ordered by deadline first, so the front of the queue is always the next timer to fire.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimerId {
     deadline: u64,
-    callback: Box<dyn Fn() -> () + Send>,
+    id: u64,
 }
 
-impl Drop for TimerEntry {
-    fn drop(&mut self) {
-        (self.callback)()
-    }
+struct TimerEntry {
+    callback: Box<dyn FnOnce() -> () + Send>,
+}
+
+struct TimerQueue {
+    entries: BTreeMap<TimerId, TimerEntry>,
+    next_id: u64,
 }
 
 lazy_static! {
-    static ref TIMER_QUEUE: Mutex<Vec<TimerEntry>> = Mutex::new(Vec::new());
+    static ref TIMER_QUEUE: Mutex<TimerQueue> = Mutex::new(TimerQueue {
+        entries: BTreeMap::new(),
+        next_id: 0
+    });
+}
+
+// 0 means the current thread can run until it blocks.
+static PREEMPT_DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+// The preemption deadline is global, but timers like the LAPIC are per-CPU and only set up on the boot CPU.
+// The APs leave both alone until they run the scheduler themselves.
+fn owns_timer() -> bool {
+    crate::per_cpu::get().is_boot_cpu
 }
 
-pub fn init() {
-    let _locked = DEFAULT_TIMER.lock();
-    // uhhhhhh idk
+// Arms the hardware timer for whichever comes first: the next timer in the queue, or preemption.
+fn program_next_deadline() {
+    if !owns_timer() {
+        return;
+    }
+
+    let next_timer = TIMER_QUEUE
+        .lock()
+        .entries
+        .keys()
+        .next()
+        .map(|id| id.deadline);
+    let preempt_deadline = match PREEMPT_DEADLINE.load(Ordering::Acquire) {
+        0 => None,
+        deadline => Some(deadline),
+    };
+
+    let mut timer_lock = DEFAULT_TIMER.lock();
+    match next_timer.into_iter().chain(preempt_deadline).min() {
+        Some(deadline) => timer_lock.set_deadline_ns(deadline),
+        None => timer_lock.clear_deadline(),
+    }
 }
 
 pub fn tick() {
-    scheduler::tick();
+    let current_time = get_counter_ns();
+
+    // Pull the expired entries out before running them, so callbacks can register timers (or wake threads) without deadlocking.
+    let expired: Vec<TimerEntry> = {
+        let mut queue_lock = TIMER_QUEUE.lock();
+        let pending = queue_lock.entries.split_off(&TimerId {
+            deadline: current_time + 1,
+            id: 0,
+        });
+        core::mem::replace(&mut queue_lock.entries, pending)
+            .into_values()
+            .collect()
+    };
+
+    let any_expired = !expired.is_empty();
+    for entry in expired {
+        (entry.callback)();
+    }
+
+    let preempt_deadline = PREEMPT_DEADLINE.load(Ordering::Acquire);
+    let should_preempt = preempt_deadline != 0 && preempt_deadline <= current_time;
+    if should_preempt {
+        // The scheduler starts a new timeslice if it switches.
+        PREEMPT_DEADLINE.store(0, Ordering::Release);
+    }
 
-    let current_time = { DEFAULT_TIMER.lock().get_counter_ns() };
+    program_next_deadline();
 
-    let mut queue_lock = TIMER_QUEUE.lock();
-    queue_lock.retain(|x| x.deadline > current_time);
+    if should_preempt || any_expired {
+        scheduler::tick();
+    }
 }
 
-pub fn register_timer(offset: u64, callback: Box<dyn Fn() -> () + Send>) {
-    let current_time = { DEFAULT_TIMER.lock().get_counter_ns() };
-    let mut queue_lock = TIMER_QUEUE.lock();
-    queue_lock.push(TimerEntry {
-        deadline: current_time + offset,
-        callback: callback,
-    });
+// Called by the scheduler whenever the runnable set changes.
+// An existing timeslice is left alone, we only arm or disarm preemption.
+pub fn update_preemption(needs_preemption: bool) {
+    if needs_preemption {
+        if PREEMPT_DEADLINE.load(Ordering::Acquire) == 0 {
+            PREEMPT_DEADLINE.store(get_counter_ns() + TIMESLICE_NS, Ordering::Release);
+            program_next_deadline();
+        }
+    } else if PREEMPT_DEADLINE.swap(0, Ordering::AcqRel) != 0 {
+        program_next_deadline();
+    }
+}
+
+// Called by the scheduler when a thread becomes runnable while we're idle, so we switch to it as soon as possible.
+pub fn preempt_now() {
+    PREEMPT_DEADLINE.store(get_counter_ns().max(1), Ordering::Release);
+    program_next_deadline();
+}
+
+// Called by the scheduler when switching to a new thread.
+pub fn restart_timeslice(needs_preemption: bool) {
+    if !owns_timer() {
+        return;
+    }
+
+    let deadline = if needs_preemption {
+        get_counter_ns() + TIMESLICE_NS
+    } else {
+        0
+    };
+    PREEMPT_DEADLINE.store(deadline, Ordering::Release);
+    program_next_deadline();
+}
+
+pub fn register_timer(offset: u64, callback: Box<dyn FnOnce() -> () + Send>) -> TimerId {
+    register_timer_at(get_counter_ns().saturating_add(offset), callback)
+}
+
+// Like register_timer, but with an absolute deadline.
+pub fn register_timer_at(deadline: u64, callback: Box<dyn FnOnce() -> () + Send>) -> TimerId {
+    let id = {
+        let mut queue_lock = TIMER_QUEUE.lock();
+        let id = TimerId {
+            deadline: deadline,
+            id: queue_lock.next_id,
+        };
+        queue_lock.next_id += 1;
+        queue_lock
+            .entries
+            .insert(id, TimerEntry { callback: callback });
+        id
+    };
+
+    program_next_deadline();
+    id
+}
+
+// Returns false if the timer already fired.
+pub fn cancel_timer(id: TimerId) -> bool {
+    let removed = TIMER_QUEUE.lock().entries.remove(&id).is_some();
+    if removed {
+        program_next_deadline();
+    }
+    removed
 }
 
 pub fn get_counter_ns() -> u64 {
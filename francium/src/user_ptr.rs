@@ -0,0 +1,100 @@
+use crate::memory::AddressSpace;
+use crate::scheduler;
+use common::os_error::{Module, Reason, ResultCode};
+use core::marker::PhantomData;
+use core::mem::{size_of, size_of_val, MaybeUninit};
+
+// Anything a syscall is handed a pointer to goes through here, and never gets dereferenced directly.
+// The range is checked against the process's regions first, so a bad pointer is an error instead of
+// a kernel fault, or the kernel reading and writing its own memory on someone's behalf.
+
+pub fn copy_from_user_in(
+    aspace: &AddressSpace,
+    address: usize,
+    buf: &mut [u8],
+) -> Result<(), ResultCode> {
+    if aspace.can_access_user(address, buf.len(), false) && aspace.read_bytes(address, buf) {
+        Ok(())
+    } else {
+        Err(ResultCode::new(Module::Kernel, Reason::InvalidArgument))
+    }
+}
+
+pub fn copy_to_user_in(
+    aspace: &mut AddressSpace,
+    address: usize,
+    buf: &[u8],
+) -> Result<(), ResultCode> {
+    if aspace.can_access_user(address, buf.len(), true) && aspace.write_bytes(address, buf) {
+        Ok(())
+    } else {
+        Err(ResultCode::new(Module::Kernel, Reason::InvalidArgument))
+    }
+}
+
+// These lock the current process, so don't call them with it already locked.
+pub fn copy_from_user(address: usize, buf: &mut [u8]) -> Result<(), ResultCode> {
+    let process = scheduler::get_current_process();
+    let process = process.lock();
+    copy_from_user_in(&process.address_space, address, buf)
+}
+
+pub fn copy_to_user(address: usize, buf: &[u8]) -> Result<(), ResultCode> {
+    let process = scheduler::get_current_process();
+    let mut process = process.lock();
+    copy_to_user_in(&mut process.address_space, address, buf)
+}
+
+// A pointer into the current process, as given to a syscall.
+#[derive(Debug)]
+pub struct UserPtr<T> {
+    address: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T> UserPtr<T> {
+    pub fn new(address: usize) -> UserPtr<T> {
+        UserPtr {
+            address,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn address(self) -> usize {
+        self.address
+    }
+
+    pub fn write(self, value: T) -> Result<(), ResultCode> {
+        let buf =
+            unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+        copy_to_user(self.address, buf)
+    }
+}
+
+impl<T: Copy> UserPtr<T> {
+    // Only for plain data, where whatever bytes the process put there make a valid T.
+    pub fn read(self) -> Result<T, ResultCode> {
+        let mut value = MaybeUninit::<T>::zeroed();
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>())
+        };
+        copy_from_user(self.address, buf)?;
+        Ok(unsafe { value.assume_init() })
+    }
+
+    // Fills out from an array of T in the process.
+    pub fn read_slice(self, out: &mut [T]) -> Result<(), ResultCode> {
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(out.as_mut_ptr() as *mut u8, size_of_val(out))
+        };
+        copy_from_user(self.address, buf)
+    }
+}
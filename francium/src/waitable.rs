@@ -1,21 +1,46 @@
 use crate::handle::HandleObject;
-use crate::process::Thread;
+use crate::process::{Thread, ThreadState};
 use crate::scheduler;
+use crate::timer;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use common::constants::WAIT_INFINITE;
+use common::handle::HandleRights;
+use common::os_error::{Module, Reason, ResultCode};
 use core::sync::atomic::{AtomicBool, Ordering};
-use smallvec::SmallVec;
 use spin::Mutex;
 
+// Wake tag for a thread whose wait ran out of time.
+pub const TIMED_OUT_TAG: usize = usize::MAX - 1;
+// Wake tag for a thread whose wait was cancelled by another thread.
+pub const CANCELLED_TAG: usize = usize::MAX - 2;
+// Wake tag for a thread whose peer closed the object it was waiting on.
+pub const CLOSED_TAG: usize = usize::MAX - 3;
+
+// Waiters are woken in the order they started waiting, so nobody can be starved by threads that arrive later.
+pub type WaiterList = VecDeque<(Arc<Thread>, usize)>;
+
+// Threads that were terminated while waiting are dropped, so they don't swallow a signal meant for someone else.
+fn pop_live_waiter(waiters: &mut WaiterList) -> Option<(Arc<Thread>, usize)> {
+    while let Some(waiter) = waiters.pop_front() {
+        if waiter.0.state.load(Ordering::Acquire) != ThreadState::Terminated {
+            return Some(waiter);
+        }
+    }
+    None
+}
+
 #[derive(Debug)]
 pub struct Waiter {
-    waiters: Mutex<SmallVec<[(Arc<Thread>, usize); 1]>>,
+    waiters: Mutex<WaiterList>,
     pending: AtomicBool,
 }
 
 impl Waiter {
     pub fn new() -> Waiter {
         Waiter {
-            waiters: Mutex::new(SmallVec::new()),
+            waiters: Mutex::new(VecDeque::new()),
             pending: AtomicBool::new(false),
         }
     }
@@ -28,36 +53,48 @@ impl Waiter {
         } else {
             self.waiters
                 .lock()
-                .push((scheduler::get_current_thread(), tag));
+                .push_back((scheduler::get_current_thread(), tag));
             return false;
         }
     }
 
-    pub fn wait(&self) {
+    // Returns the tag we were woken with, or 0 if the waiter was already pending.
+    pub fn wait(&self) -> usize {
         if !self.pending.load(Ordering::Acquire) {
             self.waiters
                 .lock()
-                .push((scheduler::get_current_thread(), 0));
-            scheduler::suspend_current_thread();
+                .push_back((scheduler::get_current_thread(), 0));
+            scheduler::suspend_current_thread()
         } else {
             self.pending.store(false, Ordering::Release);
+            0
         }
     }
 
     pub fn remove_wait(&self) {
+        self.remove_thread(scheduler::get_current_thread().id);
+    }
+
+    // Returns true if the thread was waiting here.
+    pub fn remove_thread(&self, thread_id: usize) -> bool {
         let mut waiters_locked = self.waiters.lock();
 
-        let pos = waiters_locked
-            .iter()
-            .position(|x| x.0.id == scheduler::get_current_thread().id);
+        let pos = waiters_locked.iter().position(|x| x.0.id == thread_id);
         if let Some(x) = pos {
             waiters_locked.remove(x);
+            true
+        } else {
+            false
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.waiters.lock().is_empty() && !self.pending.load(Ordering::Acquire)
+    }
+
     pub fn signal_one(&self, should_tick: bool) -> bool {
         let mut did_wake = false;
-        match self.waiters.lock().pop() {
+        match pop_live_waiter(&mut self.waiters.lock()) {
             Some(waiter) => {
                 did_wake = true;
                 scheduler::wake_thread(&waiter.0, waiter.1);
@@ -74,7 +111,7 @@ impl Waiter {
     pub fn signal_one_with_callback(&self, callback: &dyn Fn(&Arc<Thread>) -> ()) {
         let mut did_wake = false;
 
-        match self.waiters.lock().pop() {
+        match pop_live_waiter(&mut self.waiters.lock()) {
             Some(waiter) => {
                 callback(&waiter.0);
                 did_wake = true;
@@ -98,6 +135,32 @@ impl Waiter {
             .for_each(drop);
     }
 
+    // Unlike signal_one, this doesn't leave the waiter pending if nobody was woken.
+    pub fn signal_n(&self, count: usize) -> usize {
+        let woken = self.take_waiters(count);
+        let num_woken = woken.len();
+        for x in woken {
+            scheduler::wake_thread(&x.0, x.1);
+        }
+        num_woken
+    }
+
+    pub fn take_waiters(&self, count: usize) -> WaiterList {
+        let mut waiters_locked = self.waiters.lock();
+        let mut taken = WaiterList::new();
+        while taken.len() < count {
+            match pop_live_waiter(&mut waiters_locked) {
+                Some(waiter) => taken.push_back(waiter),
+                None => break,
+            }
+        }
+        taken
+    }
+
+    pub fn add_waiters(&self, waiters: WaiterList) {
+        self.waiters.lock().extend(waiters);
+    }
+
     pub fn clear(&self) {
         self.pending.store(false, Ordering::Release);
     }
@@ -106,8 +169,8 @@ impl Waiter {
 pub trait Waitable {
     fn get_waiter(&self) -> &Waiter;
 
-    fn wait(&self) {
-        self.get_waiter().wait();
+    fn wait(&self) -> usize {
+        self.get_waiter().wait()
     }
 
     fn signal_one(&self) {
@@ -135,20 +198,76 @@ pub trait Waitable {
     }
 }
 
-const MAX_HANDLES: usize = 128;
+pub const MAX_HANDLES: usize = 128;
 const INVALID_HANDLE: HandleObject = HandleObject::Invalid;
 
-// returns index
-pub fn wait_handles(handles: &[u32]) -> usize {
+// Takes the thread off everything it was waiting on.
+// Returns false if it was already gone from one of them, which means a signal took it off and is waking it.
+fn remove_thread_from_all(handle_objects: &[HandleObject], thread_id: usize) -> bool {
+    let mut all_removed = true;
+    for handle in handle_objects.iter() {
+        let removed = match handle {
+            // What handles are waitable?
+            HandleObject::Port(port) => {
+                port.get_waiter().remove_thread(thread_id)
+                    | port.get_close_waiter().remove_thread(thread_id)
+            }
+            HandleObject::ServerSession(server_session) => {
+                server_session.get_waiter().remove_thread(thread_id)
+            }
+            HandleObject::ClientSession(client_session) => {
+                client_session.get_waiter().remove_thread(thread_id)
+            }
+            HandleObject::Event(event) => event.get_waiter().remove_thread(thread_id),
+            HandleObject::Timer(timer) => timer.get_waiter().remove_thread(thread_id),
+            HandleObject::Process(process) => process.lock().exit_waiter.remove_thread(thread_id),
+            _ => true,
+        };
+        all_removed &= removed;
+    }
+    all_removed
+}
+
+// Wakes a thread out of wait_handles early, if it's in there. Returns false if it wasn't.
+pub fn interrupt_wait(thread: &Arc<Thread>, tag: usize) -> bool {
+    if thread.in_cancellable_wait.swap(false, Ordering::AcqRel) {
+        // Take it off the waiter lists first, or a signal could pick the stale entry and be lost.
+        // If a signal got there first, it's already waking the thread and wins.
+        let wait_objects = core::mem::take(&mut *thread.wait_objects.lock());
+        if remove_thread_from_all(&wait_objects, thread.id) {
+            scheduler::wake_thread(thread, tag);
+        }
+        true
+    } else {
+        false
+    }
+}
+
+pub fn cancel_wait(thread: &Arc<Thread>) {
+    if !interrupt_wait(thread, CANCELLED_TAG) {
+        thread.cancel_pending.store(true, Ordering::Release);
+    }
+}
+
+// Returns the index of the handle that was signalled.
+// A timeout of 0 just polls, WAIT_INFINITE never times out.
+pub fn wait_handles(handles: &[u32], timeout_ns: u64) -> Result<usize, ResultCode> {
     let mut handle_objects = [INVALID_HANDLE; MAX_HANDLES];
     let handle_objects = &mut handle_objects[0..handles.len()];
+    let mut can_accept = [false; MAX_HANDLES];
 
     {
         let process_locked = scheduler::get_current_process();
         let process = process_locked.lock();
 
         for (i, handle) in handles.iter().enumerate() {
-            handle_objects[i] = process.handle_table.get_object(*handle);
+            handle_objects[i] = process
+                .handle_table
+                .get_object_checked(*handle, HandleRights::WAIT)?;
+            can_accept[i] = process
+                .handle_table
+                .get_rights(*handle)
+                .contains(HandleRights::ACCEPT);
         }
     }
 
@@ -158,7 +277,7 @@ pub fn wait_handles(handles: &[u32]) -> usize {
     for (index, handle) in handle_objects.iter().enumerate() {
         match handle {
             // What handles are waitable?
-            HandleObject::Port(port) => {
+            HandleObject::Port(port) if can_accept[index] => {
                 // XXX: Big hack, we love to see it. Ordering here is important, post_wait has to remove the pending status first.
                 if port.post_wait(index) || port.queue.lock().len() > 0 {
                     any_pending = true;
@@ -167,9 +286,24 @@ pub fn wait_handles(handles: &[u32]) -> usize {
                 }
             }
 
+            HandleObject::Port(port) => {
+                // Without the right to accept, the only thing to wait for is the port closing.
+                port.get_close_waiter().post_wait(index);
+                if port.is_closed() {
+                    any_pending = true;
+                    tag = index;
+                    break;
+                }
+            }
+
             HandleObject::ServerSession(server_session) => {
                 // XXX: Big hack, we love to see it. Ordering here is important, post_wait has to remove the pending status first.
-                if server_session.post_wait(index) || server_session.queue.lock().len() > 0 {
+                // A session whose client went away stays signalled, so the server notices and closes its end.
+                if server_session.post_wait(index)
+                    || server_session.queue.lock().len() > 0
+                    || server_session.has_notifications()
+                    || server_session.is_client_closed()
+                {
                     any_pending = true;
                     tag = index;
                     break;
@@ -177,7 +311,7 @@ pub fn wait_handles(handles: &[u32]) -> usize {
             }
 
             HandleObject::ClientSession(client_session) => {
-                if client_session.post_wait(index) {
+                if client_session.post_wait(index) || client_session.is_server_closed() {
                     any_pending = true;
                     tag = index;
                     break;
@@ -201,36 +335,70 @@ pub fn wait_handles(handles: &[u32]) -> usize {
                 }
             }
 
+            HandleObject::Timer(timer) => {
+                if timer.post_wait(index) {
+                    any_pending = true;
+                    tag = index;
+                    break;
+                }
+            }
+
+            HandleObject::Process(process) => {
+                // Exiting is permanent, so this stays signalled rather than being consumed by the first waiter.
+                let process_locked = process.lock();
+                if process_locked.exit_reason.is_some() {
+                    any_pending = true;
+                    tag = index;
+                    break;
+                }
+                process_locked.exit_waiter.post_wait(index);
+            }
+
             _ => {}
         }
     }
 
     if !any_pending {
-        tag = scheduler::suspend_current_thread();
-    }
-
-    for handle in handle_objects.iter() {
-        match handle {
-            // What handles are waitable?
-            HandleObject::Port(port) => {
-                port.remove_wait();
-            }
-
-            HandleObject::ServerSession(server_session) => {
-                server_session.remove_wait();
-            }
+        let current_thread = scheduler::get_current_thread();
 
-            HandleObject::ClientSession(client_session) => {
-                client_session.remove_wait();
-            }
-
-            HandleObject::Event(event) => {
-                event.remove_wait();
+        if current_thread.cancel_pending.swap(false, Ordering::AcqRel) {
+            tag = CANCELLED_TAG;
+        } else if timeout_ns == 0 {
+            tag = TIMED_OUT_TAG;
+        } else {
+            let timeout_timer = if timeout_ns != WAIT_INFINITE {
+                let thread = current_thread.clone();
+                Some(timer::register_timer(
+                    timeout_ns,
+                    Box::new(move || {
+                        interrupt_wait(&thread, TIMED_OUT_TAG);
+                    }),
+                ))
+            } else {
+                None
+            };
+
+            *current_thread.wait_objects.lock() = handle_objects.to_vec();
+            current_thread
+                .in_cancellable_wait
+                .store(true, Ordering::Release);
+            tag = scheduler::suspend_current_thread();
+            current_thread
+                .in_cancellable_wait
+                .store(false, Ordering::Release);
+            current_thread.wait_objects.lock().clear();
+
+            if let Some(timeout_timer) = timeout_timer {
+                timer::cancel_timer(timeout_timer);
             }
-
-            _ => {}
         }
     }
 
-    tag
+    remove_thread_from_all(handle_objects, scheduler::get_current_thread().id);
+
+    match tag {
+        TIMED_OUT_TAG => Err(ResultCode::new(Module::Kernel, Reason::TimedOut)),
+        CANCELLED_TAG => Err(ResultCode::new(Module::Kernel, Reason::Cancelled)),
+        index => Ok(index),
+    }
 }
@@ -20,6 +20,10 @@ struct MethodInfo {
     inputs: Vec<Ty>,
     output: String,
     is_async: Option<bool>,
+    // One-way, sent with ipc_notify. The client doesn't wait, and the server's return value is dropped.
+    is_notification: Option<bool>,
+    // The server method gets an IPCContext saying who sent the message, before the inputs.
+    with_context: Option<bool>,
 }
 
 struct Method {
@@ -29,6 +33,8 @@ struct Method {
     inputs: Vec<TokenStream2>,
     output_type: Type,
     is_async: Option<bool>,
+    is_notification: bool,
+    with_context: bool,
 }
 
 impl Method {
@@ -57,41 +63,185 @@ impl Method {
             inputs: inputs,
             output_type: output_type,
             is_async: info.is_async,
+            is_notification: info.is_notification.unwrap_or(false),
+            with_context: info.with_context.unwrap_or(false),
+        }
+    }
+
+    // Notifications only tell the client whether the message could be sent.
+    fn client_output_type(&self) -> TokenStream2 {
+        if self.is_notification {
+            quote!(crate::os_error::OSResult<()>)
+        } else {
+            let output_type = &self.output_type;
+            quote!(#output_type)
+        }
+    }
+
+    // Methods returning OSResult can report a message that didn't fit as an error, anything else has to give up.
+    fn returns_result(&self) -> bool {
+        match &self.output_type {
+            syn::Type::Path(x) => x
+                .path
+                .segments
+                .last()
+                .map_or(false, |last| last.ident == "OSResult"),
+            _ => false,
+        }
+    }
+
+    // Arguments to the server method, the context first if it wants one.
+    fn server_args(&self) -> TokenStream2 {
+        let input_names = &self.input_names;
+        if self.with_context {
+            quote!(__ipc_context, #(#input_names),*)
+        } else {
+            quote!(#(#input_names),*)
+        }
+    }
+
+    fn read_context(&self) -> TokenStream2 {
+        if self.with_context {
+            quote!(let __ipc_context = request_msg.context();)
+        } else {
+            quote!()
+        }
+    }
+
+    // If the request can't be read or the reply doesn't fit, reply with the error if we can.
+    // Otherwise send back an empty reply, the client fails to read it and gives up.
+    fn reply_with_error(&self, reply_buffer: TokenStream2) -> TokenStream2 {
+        let output_type = &self.output_type;
+        if self.returns_result() {
+            quote! {
+                let res: #output_type = Err(err);
+                process::ipc::message::write_reply(#reply_buffer, res).unwrap();
+            }
+        } else {
+            quote! {
+                let _ = err;
+                process::ipc::message::write_reply(#reply_buffer, ()).unwrap();
+            }
         }
     }
 
     fn server(&self) -> TokenStream2 {
+        if self.is_notification {
+            return self.server_notification();
+        }
+
         let method_name = format_ident!("{}", self.name);
         let method_id: u32 = self.id;
-        let input_names = &self.input_names;
+        let server_args = self.server_args();
+        let read_context = self.read_context();
         let inputs = &self.inputs;
         let output_type = &self.output_type;
-        let maybe_await = if let Some(is_async) = self.is_async {
-            if is_async {
-                quote!(.await)
-            } else {
-                quote!()
+        let is_async = self.is_async.unwrap_or(false);
+        let maybe_await = if is_async { quote!(.await) } else { quote!() };
+
+        // Async methods reply from their own task, through that thread's IPC_BUFFER.
+        // Everything else is handled right away, and the reply goes back in the buffer the request came in, for the server loop to send.
+        let reply_buffer = if is_async {
+            quote!(unsafe { &mut IPC_BUFFER })
+        } else {
+            quote!(ipc_buffer)
+        };
+
+        let on_error = self.reply_with_error(reply_buffer.clone());
+        let write_reply = quote! {
+            let res: #output_type = self.#method_name (#server_args) #maybe_await;
+            if let Err(err) = process::ipc::message::write_reply(#reply_buffer, res) {
+                #on_error
+            }
+        };
+
+        // A request that can't be read is answered straight away, before anything gets spawned.
+        let on_read_error = self.reply_with_error(quote!(ipc_buffer));
+        let read_inputs = quote! {
+            #(
+                let #inputs = match request_msg.read() {
+                    Ok(x) => x,
+                    Err(err) => {
+                        #on_read_error
+                        return true;
+                    }
+                };
+            )*
+        };
+
+        let handle_request = if is_async {
+            quote! {
+                tokio::spawn(async move {
+                    #write_reply
+
+                    // The session can be closed while we're busy, if the client went away. Nobody's left to reply to, so that's fine.
+                    unsafe { let _ = crate::syscalls::ipc_reply(h, &mut IPC_BUFFER); }
+                });
+                false
             }
         } else {
-            quote!()
+            quote! {
+                #write_reply
+                true
+            }
         };
 
         quote! {
             #method_id => {
+                // Whoever sent this isn't waiting for a reply.
+                if request_msg.header.is_notification() {
+                    return false;
+                }
+
                 request_msg.read_translates();
 
-                #(let #inputs = request_msg.read();)*
+                #read_context
+                #read_inputs
 
-                tokio::spawn(async move {
-                    let res: #output_type = self.#method_name (#(#input_names),*) #maybe_await;
-                    let mut reply_msg = unsafe { process::ipc::message::IPCMessage::new(&mut IPC_BUFFER) };
-                    reply_msg.write(res);
-                    reply_msg.write_translates();
-                    reply_msg.write_header_for(0);
+                #handle_request
+            }
+        }
+    }
+
+    fn server_notification(&self) -> TokenStream2 {
+        let method_name = format_ident!("{}", self.name);
+        let method_id: u32 = self.id;
+        let server_args = self.server_args();
+        let read_context = self.read_context();
+        let inputs = &self.inputs;
 
-                    unsafe { crate::syscalls::ipc_reply(h, &mut IPC_BUFFER).unwrap(); }
+        let call = if self.is_async.unwrap_or(false) {
+            quote! {
+                tokio::spawn(async move {
+                    let _ = self.#method_name (#server_args).await;
                 });
             }
+        } else {
+            quote! {
+                let _ = self.#method_name (#server_args);
+            }
+        };
+
+        quote! {
+            #method_id => {
+                // Sent as a request by mistake, don't leave the client waiting.
+                if !request_msg.header.is_notification() {
+                    process::ipc::message::write_reply(ipc_buffer, ()).unwrap();
+                    return true;
+                }
+
+                #read_context
+                // Malformed notifications get dropped, there's nobody to tell.
+                #(
+                    let #inputs = match request_msg.read() {
+                        Ok(x) => x,
+                        Err(_) => return false,
+                    };
+                )*
+
+                #call
+                false
+            }
         }
     }
 
@@ -102,15 +252,18 @@ impl Method {
 
         let input_names = &self.input_names;
         let inputs = &self.inputs;
-        let output_type = &self.output_type;
+        let output_type = self.client_output_type();
 
         let body = self.client_body();
+        let async_client = self.async_client(quote!(#(#inputs),*), quote!(#(#input_names),*));
         quote! {
             pub fn #method_name ( #(#inputs),* ) -> #output_type {
                 let __ipc_handle = #ipc_handle_accessor();
                 #method_name_with_handle(__ipc_handle, #(#input_names),*)
             }
 
+            #async_client
+
             #body
         }
     }
@@ -121,30 +274,86 @@ impl Method {
 
         let input_names = &self.input_names;
         let inputs = &self.inputs;
-        let output_type = &self.output_type;
+        let output_type = self.client_output_type();
 
         let body = self.client_body();
+        let async_client = self.async_client(
+            quote!(__ipc_handle: Handle, #(#inputs),*),
+            quote!(__ipc_handle, #(#input_names),*),
+        );
 
         quote! {
             pub fn #method_name ( __ipc_handle: Handle, #(#inputs),* ) -> #output_type {
                 #method_name_with_handle(__ipc_handle, #(#input_names),*)
             }
 
+            #async_client
+
             #body
         }
     }
 
+    // For callers on a tokio runtime. The request blocks until the reply comes back, so it's made from one of tokio's blocking threads,
+    // which each have their own IPC_BUFFER, and the runtime carries on in the meantime.
+    // Requests on the same handle still go one at a time, see session_lock.
+    // Notifications don't wait for anything, so they don't get one.
+    fn async_client(&self, params: TokenStream2, args: TokenStream2) -> TokenStream2 {
+        if self.is_notification {
+            return quote!();
+        }
+
+        let method_name = format_ident!("{}", self.name);
+        let method_name_async = format_ident!("{}_async", self.name);
+        let output_type = self.client_output_type();
+
+        // Same as a failed request: an error if the method can return one, otherwise there's nothing to hand back.
+        let on_join_error = if self.returns_result() {
+            quote! { Err(err.into()) }
+        } else {
+            quote! { panic!("IPC request task failed: {:?}", err) }
+        };
+
+        quote! {
+            pub async fn #method_name_async ( #params ) -> #output_type {
+                match tokio::task::spawn_blocking(move || #method_name(#args)).await {
+                    Ok(res) => res,
+                    Err(err) => #on_join_error,
+                }
+            }
+        }
+    }
+
     fn client_body(&self) -> syn::__private::TokenStream2 {
+        if self.is_notification {
+            return self.client_notification_body();
+        }
+
         let input_names = &self.input_names;
         let inputs = &self.inputs;
         let output_type = &self.output_type;
 
+        let on_error = if self.returns_result() {
+            quote! { return Err(err); }
+        } else {
+            quote! { panic!("IPC request failed: {:?}", err); }
+        };
+
         let dispatch_output = if let syn::Type::Tuple(x) = &self.output_type && x.elems.is_empty() {
             quote! {}
         } else {
             quote! {
-                let out: #output_type = reply_msg.read();
-                out
+                let mut reply_msg = unsafe { crate::ipc::message::IPCMessage::new(&mut IPC_BUFFER) };
+                if let Err(err) = reply_msg.read_header() {
+                    #on_error
+                }
+                reply_msg.read_translates();
+
+                match reply_msg.read() {
+                    Ok(out) => out,
+                    Err(err) => {
+                        #on_error
+                    }
+                }
             }
         };
 
@@ -159,23 +368,208 @@ impl Method {
 
         quote! {
             fn #method_name ( __ipc_handle: Handle, #(#inputs),* ) -> #output_type {
+                let session_lock = crate::ipc::session_lock(__ipc_handle);
+                let _session_guard = session_lock.lock().unwrap();
+
                 let mut request_msg = unsafe { crate::ipc::message::IPCMessage::new(&mut IPC_BUFFER) };
 
                 #write_inputs
 
                 request_msg.write_header_for(#method_id);
                 request_msg.write_translates();
+                if let Err(err) = request_msg.check() {
+                    #on_error
+                }
 
-                unsafe { crate::syscalls::ipc_request(__ipc_handle, &mut IPC_BUFFER).unwrap(); }
-
-                let mut reply_msg = unsafe { crate::ipc::message::IPCMessage::new(&mut IPC_BUFFER) };
-                reply_msg.read_header();
-                reply_msg.read_translates();
+                // Fails with SessionClosed if the server went away.
+                if let Err(err) = unsafe { crate::syscalls::ipc_request(__ipc_handle, &mut IPC_BUFFER) } {
+                    #on_error
+                }
 
                 #dispatch_output
             }
         }
     }
+
+    fn client_notification_body(&self) -> syn::__private::TokenStream2 {
+        let input_names = &self.input_names;
+        let inputs = &self.inputs;
+
+        let write_inputs = if input_names.len() == 0 {
+            quote! {}
+        } else {
+            quote! { #(request_msg.write(#input_names));*; }
+        };
+
+        let method_name = format_ident!("{}_with_handle", self.name);
+        let method_id: u32 = self.id;
+
+        quote! {
+            fn #method_name ( __ipc_handle: Handle, #(#inputs),* ) -> crate::os_error::OSResult<()> {
+                let mut request_msg = unsafe { crate::ipc::message::IPCMessage::new(&mut IPC_BUFFER) };
+
+                #write_inputs
+
+                request_msg.write_header_for(#method_id);
+                request_msg.write_translates();
+                request_msg.check()?;
+
+                unsafe { crate::syscalls::ipc_notify(__ipc_handle, &mut IPC_BUFFER) }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct VariantInfo {
+    name: String,
+    // Sent ahead of the fields, as a u32.
+    id: u32,
+    #[serde(default)]
+    fields: Vec<Ty>,
+}
+
+// A struct if it has fields, an enum if it has variants.
+#[derive(Debug, Deserialize)]
+struct TypeInfo {
+    name: String,
+    #[serde(default)]
+    derive: Vec<String>,
+    fields: Option<Vec<Ty>>,
+    variants: Option<Vec<VariantInfo>>,
+}
+
+fn field_names(fields: &[Ty]) -> Vec<Ident> {
+    fields.iter().map(|x| format_ident!("{}", x.name)).collect()
+}
+
+fn field_types(fields: &[Ty]) -> Vec<Type> {
+    fields
+        .iter()
+        .map(|x| syn::parse_str(&x.ty).unwrap())
+        .collect()
+}
+
+impl TypeInfo {
+    // krate is the path to libprocess, which is just crate inside it.
+    fn generate(&self, krate: &TokenStream2) -> TokenStream2 {
+        let derives: Vec<SynPath> = self
+            .derive
+            .iter()
+            .map(|x| syn::parse_str(x).unwrap())
+            .collect();
+        let derive = if derives.is_empty() {
+            quote!()
+        } else {
+            quote!(#[derive(#(#derives),*)])
+        };
+
+        match (&self.fields, &self.variants) {
+            (Some(fields), None) if !fields.is_empty() => {
+                self.generate_struct(fields, derive, krate)
+            }
+            (None, Some(variants)) if !variants.is_empty() => {
+                self.generate_enum(variants, derive, krate)
+            }
+            _ => panic!("Type {} needs either fields or variants!", self.name),
+        }
+    }
+
+    fn generate_struct(
+        &self,
+        fields: &[Ty],
+        derive: TokenStream2,
+        krate: &TokenStream2,
+    ) -> TokenStream2 {
+        let name = format_ident!("{}", self.name);
+        let names = field_names(fields);
+        let types = field_types(fields);
+
+        quote! {
+            #derive
+            pub struct #name {
+                #(pub #names: #types),*
+            }
+
+            impl #krate::ipc::message::IPCValue for #name {
+                fn read(msg: &mut #krate::ipc::message::IPCMessage) -> #krate::os_error::OSResult<#name> {
+                    Ok(#name {
+                        #(#names: msg.read()?),*
+                    })
+                }
+
+                fn write(msg: &mut #krate::ipc::message::IPCMessage, val: &#name) {
+                    #(<#types as #krate::ipc::message::IPCValue>::write(msg, &val.#names);)*
+                }
+            }
+        }
+    }
+
+    fn generate_enum(
+        &self,
+        variants: &[VariantInfo],
+        derive: TokenStream2,
+        krate: &TokenStream2,
+    ) -> TokenStream2 {
+        let name = format_ident!("{}", self.name);
+
+        let mut declarations = Vec::new();
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+        for variant in variants {
+            let variant_name = format_ident!("{}", variant.name);
+            let id = variant.id;
+            let names = field_names(&variant.fields);
+            let types = field_types(&variant.fields);
+
+            if variant.fields.is_empty() {
+                declarations.push(quote!(#variant_name));
+                reads.push(quote!(#name::#variant_name));
+                writes.push(quote! {
+                    #name::#variant_name => msg.write(#id)
+                });
+            } else {
+                declarations.push(quote!(#variant_name { #(#names: #types),* }));
+                reads.push(quote!(#name::#variant_name { #(#names: msg.read()?),* }));
+                writes.push(quote! {
+                    #name::#variant_name { #(#names),* } => {
+                        msg.write(#id);
+                        #(<#types as #krate::ipc::message::IPCValue>::write(msg, #names);)*
+                    }
+                });
+            }
+        }
+
+        let ids: Vec<u32> = variants.iter().map(|x| x.id).collect();
+
+        quote! {
+            #derive
+            pub enum #name {
+                #(#declarations),*
+            }
+
+            impl #krate::ipc::message::IPCValue for #name {
+                fn read(msg: &mut #krate::ipc::message::IPCMessage) -> #krate::os_error::OSResult<#name> {
+                    let id: u32 = msg.read()?;
+                    match id {
+                        #(#ids => Ok(#reads),)*
+                        _ => Err(#krate::ipc::message::malformed_message()),
+                    }
+                }
+
+                fn write(msg: &mut #krate::ipc::message::IPCMessage, val: &#name) {
+                    match val {
+                        #(#writes),*
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn generate_types(types: &[TypeInfo], krate: TokenStream2) -> TokenStream2 {
+    let types: Vec<_> = types.iter().map(|x| x.generate(&krate)).collect();
+    quote!(#(#types)*)
 }
 
 #[derive(Debug, Deserialize)]
@@ -186,6 +580,9 @@ struct ServerConfig {
     main_interface: Interface,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     sub_interfaces: Vec<Interface>,
+    // Structs and enums used by the methods, generated for both the client and the server.
+    #[serde(default)]
+    types: Vec<TypeInfo>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -236,19 +633,38 @@ fn generate_server_interface(interface: &Interface) -> String {
 
     let server_impl = quote!(
         impl IPCSession for #session_name {
-            fn process(self: std::sync::Arc<Self>, h: Handle, ipc_buffer: &mut [u8]) {
-                self.process_internal(h, ipc_buffer);
+            fn process(self: std::sync::Arc<Self>, h: Handle, ipc_buffer: &mut [u8]) -> bool {
+                self.process_internal(h, ipc_buffer)
             }
         }
 
         impl #session_name {
-            fn process_internal(self: std::sync::Arc<Self>, h: Handle, ipc_buffer: &mut [u8]) {
+            fn process_internal(self: std::sync::Arc<Self>, h: Handle, ipc_buffer: &mut [u8]) -> bool {
+                // Only methods that reply by themselves need the handle.
+                let _ = h;
+
                 let mut request_msg = process::ipc::message::IPCMessage::new(ipc_buffer);
-                request_msg.read_header();
+                if let Err(err) = request_msg.read_header() {
+                    let res: process::os_error::OSResult<()> = Err(err);
+                    process::ipc::message::write_reply(ipc_buffer, res).unwrap();
+                    return true;
+                }
 
                 match request_msg.header.id {
                     #(#server_methods),*,
-                    _ => { panic!("Unexpected IPC message ID!") }
+                    _ => {
+                        // Nobody's waiting on a notification, so there's nobody to tell.
+                        if request_msg.header.is_notification() {
+                            return false;
+                        }
+
+                        let res: process::os_error::OSResult<()> = Err(process::os_error::OSError::new(
+                            process::os_error::Module::LibProcess,
+                            process::os_error::Reason::NotImplemented,
+                        ));
+                        process::ipc::message::write_reply(ipc_buffer, res).unwrap();
+                        true
+                    }
                 }
             }
         }
@@ -267,6 +683,7 @@ pub fn generate_server(path: &str) {
     use std::sync::MutexGuard;"
         .to_string();
 
+    let types_impl = generate_types(&spec.types, quote!(process)).to_string();
     let server_impl = generate_server_ipcserver_impl(&spec);
     let server_main_impl = generate_server_interface(&spec.main_interface);
     let server_sub_impl = spec
@@ -277,7 +694,7 @@ pub fn generate_server(path: &str) {
         .join("\n");
     fs::write(
         dest_path,
-        header + &server_impl + &server_main_impl + &server_sub_impl,
+        header + &types_impl + &server_impl + &server_main_impl + &server_sub_impl,
     )
     .unwrap();
 
@@ -305,9 +722,13 @@ pub fn generate_client(path: &str) {
         .map(|x| Method::new(x).sub_client())
         .collect();
 
+    let types_impl = generate_types(&spec.types, quote!(crate));
+
     let client_impl = quote! {
         use crate::ipc::message::IPC_BUFFER;
 
+        #types_impl
+
         #(#client_methods)*
 
         #(#sub_client_methods)*
@@ -1,3 +1,2 @@
-pub const GET_FS: u32 = 0;
-pub const SET_FS: u32 = 1;
-pub const GET_ACPI_BASE: u32 = 2;
+// Timeout value for blocking syscalls that should never time out.
+pub const WAIT_INFINITE: u64 = u64::MAX;
@@ -2,3 +2,37 @@
 #[repr(transparent)]
 pub struct Handle(pub u32);
 pub const INVALID_HANDLE: Handle = Handle(0xffffffff);
+
+// What a handle is allowed to be used for. Handles can only ever lose rights, never gain them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct HandleRights(pub u32);
+
+impl HandleRights {
+    pub const NONE: HandleRights = HandleRights(0);
+    // Query an object, e.g. a process's exit info.
+    pub const READ: HandleRights = HandleRights(1 << 0);
+    // Modify an object, e.g. arm a timer, write to or start a process, or send on a session.
+    pub const WRITE: HandleRights = HandleRights(1 << 1);
+    pub const SIGNAL: HandleRights = HandleRights(1 << 2);
+    pub const WAIT: HandleRights = HandleRights(1 << 3);
+    pub const MAP: HandleRights = HandleRights(1 << 4);
+    // Copy or move the handle to another process over IPC.
+    pub const TRANSFER: HandleRights = HandleRights(1 << 5);
+    pub const DUPLICATE: HandleRights = HandleRights(1 << 6);
+    pub const CONNECT: HandleRights = HandleRights(1 << 7);
+    pub const ACCEPT: HandleRights = HandleRights(1 << 8);
+    pub const ALL: HandleRights = HandleRights((1 << 9) - 1);
+
+    pub fn contains(self, other: HandleRights) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for HandleRights {
+    type Output = HandleRights;
+
+    fn bitor(self, rhs: HandleRights) -> HandleRights {
+        HandleRights(self.0 | rhs.0)
+    }
+}
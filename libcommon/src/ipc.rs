@@ -1,14 +1,28 @@
 use crate::Handle;
 
 pub const MAX_TRANSLATE: usize = 4;
+// Messages can be up to a page, the kernel only copies as much as the header says is used.
+pub const IPC_BUFFER_SIZE: usize = 4096;
+// The packed header, then the sender's process id and the session's badge. The kernel fills those two in, whatever the sender put there.
+pub const IPC_HEADER_SIZE: usize = 24;
+pub const IPC_SENDER_OFFSET: usize = 8;
+pub const IPC_BADGE_OFFSET: usize = 16;
+pub const TRANSLATE_ENTRY_SIZE: usize = 16;
 pub const TRANSLATE_TYPE_MOVE_HANDLE: u64 = 1;
 pub const TRANSLATE_TYPE_COPY_HANDLE: u64 = 2;
 
+// ipc_reply_and_receive gives this instead of an index when it was the reply that failed, and nothing was received.
+pub const REPLY_FAILED_INDEX: usize = usize::MAX;
+
+// Set by the kernel on messages sent with ipc_notify. Nobody is waiting for a reply to them.
+pub const IPC_FLAG_NOTIFICATION: u16 = 1;
+
 #[derive(Debug)]
 pub struct IPCHeader {
     pub id: u32,
     pub size: usize,
     pub translate_count: usize,
+    pub flags: u16,
 }
 
 #[repr(transparent)]
@@ -29,7 +43,7 @@ pub enum TranslateEntry {
 }
 
 impl TranslateEntry {
-    pub fn read(buffer: &[u8; 16]) -> TranslateEntry {
+    pub fn read(buffer: &[u8; TRANSLATE_ENTRY_SIZE]) -> TranslateEntry {
         let translate_type = u64::from_le_bytes(buffer[0..8].try_into().unwrap());
         let translate_payload = u64::from_le_bytes(buffer[8..16].try_into().unwrap());
 
@@ -40,9 +54,8 @@ impl TranslateEntry {
             TRANSLATE_TYPE_COPY_HANDLE => {
                 TranslateEntry::CopyHandle(Handle(translate_payload as u32))
             }
-            _ => {
-                unimplemented!();
-            }
+            // Whatever sent this was broken, but that's no reason to take down whoever reads it.
+            _ => TranslateEntry::None,
         }
     }
 
@@ -56,6 +69,9 @@ impl TranslateEntry {
                 buffer[0..8].copy_from_slice(&u64::to_le_bytes(TRANSLATE_TYPE_COPY_HANDLE));
                 buffer[8..16].copy_from_slice(&u64::to_le_bytes(handle.0 as u64));
             }
+            TranslateEntry::None => {
+                buffer[0..16].fill(0);
+            }
             _ => {
                 unimplemented!();
             }
@@ -63,29 +79,86 @@ impl TranslateEntry {
     }
 }
 
+// The packed header is 16 bits of message id, 8 bits of translate count, 8 bits of magic, 16 bits of size, then 16 bits of flags.
+// size includes the header, the translate entries come straight after.
 impl IPCHeader {
-    pub fn pack(header: &IPCHeader) -> u32 {
-        assert!(header.size < 256);
-        assert!(header.translate_count < 256);
-
-        let packed = header.id
-            | (((header.size & 0xff) as u32) << 8)
-            | (((header.translate_count & 0xff) as u32) << 16)
-            | (0xaa << 24);
+    pub fn pack(header: &IPCHeader) -> u64 {
+        assert!(header.id <= 0xffff);
+        assert!(header.size <= IPC_BUFFER_SIZE);
+        assert!(header.translate_count <= MAX_TRANSLATE);
+
+        let packed = (header.id as u64)
+            | ((header.translate_count as u64) << 16)
+            | (0xaa << 24)
+            | ((header.size as u64) << 32)
+            | ((header.flags as u64) << 48);
         packed
     }
 
-    pub fn unpack(packed: u32) -> IPCHeader {
-        let message_id = packed & 0xff;
-        let message_size = (packed & (0xff << 8)) >> 8;
-        let message_translate_count = (packed & (0xff << 16)) >> 16;
+    // Returns None if the header is malformed.
+    pub fn unpack(packed: u64) -> Option<IPCHeader> {
+        let message_id = packed & 0xffff;
+        let message_translate_count = (packed >> 16) & 0xff;
+        let message_size = (packed >> 32) & 0xffff;
+        let message_flags = packed >> 48;
 
-        assert!((packed & (0xff << 24)) >> 24 == 0xaa);
+        if (packed >> 24) & 0xff != 0xaa {
+            return None;
+        }
 
-        IPCHeader {
-            id: message_id,
+        let header = IPCHeader {
+            id: message_id as u32,
             size: message_size as usize,
             translate_count: message_translate_count as usize,
+            flags: message_flags as u16,
+        };
+
+        if header.size < IPC_HEADER_SIZE
+            || header.translate_count > MAX_TRANSLATE
+            || header.size + header.translate_count * TRANSLATE_ENTRY_SIZE > IPC_BUFFER_SIZE
+        {
+            return None;
         }
+
+        Some(header)
+    }
+
+    // Reads the packed header from the start of a message. Returns None if it's malformed.
+    pub fn read_from(buffer: &[u8]) -> Option<IPCHeader> {
+        IPCHeader::unpack(u64::from_le_bytes(buffer[0..8].try_into().unwrap()))
+    }
+
+    pub fn write_to(buffer: &mut [u8], header: &IPCHeader) {
+        buffer[0..8].copy_from_slice(&u64::to_le_bytes(IPCHeader::pack(header)));
+    }
+
+    // Only the kernel should call this, anything the sender writes there is overwritten.
+    pub fn write_sender(buffer: &mut [u8], process_id: usize, badge: u64) {
+        buffer[IPC_SENDER_OFFSET..IPC_SENDER_OFFSET + 8]
+            .copy_from_slice(&u64::to_le_bytes(process_id as u64));
+        buffer[IPC_BADGE_OFFSET..IPC_BADGE_OFFSET + 8].copy_from_slice(&u64::to_le_bytes(badge));
+    }
+
+    pub fn read_sender(buffer: &[u8]) -> (usize, u64) {
+        let process_id = u64::from_le_bytes(
+            buffer[IPC_SENDER_OFFSET..IPC_SENDER_OFFSET + 8]
+                .try_into()
+                .unwrap(),
+        );
+        let badge = u64::from_le_bytes(
+            buffer[IPC_BADGE_OFFSET..IPC_BADGE_OFFSET + 8]
+                .try_into()
+                .unwrap(),
+        );
+        (process_id as usize, badge)
+    }
+
+    pub fn is_notification(&self) -> bool {
+        self.flags & IPC_FLAG_NOTIFICATION != 0
+    }
+
+    // How many bytes of the buffer the message actually uses.
+    pub fn used_length(&self) -> usize {
+        self.size + self.translate_count * TRANSLATE_ENTRY_SIZE
     }
 }
@@ -3,6 +3,7 @@ pub mod constants;
 pub mod handle;
 pub mod ipc;
 pub mod os_error;
+pub mod process;
 pub mod system_info;
 pub use handle::*;
 
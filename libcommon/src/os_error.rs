@@ -20,6 +20,7 @@ pub enum Module {
     Fs = 3,
     Pcie = 4,
     LibProcess = 5,
+    Loader = 6,
     Unknown = 0xffff,
 }
 
@@ -32,6 +33,14 @@ pub enum Reason {
     InvalidHandle = 3,
     NotFound = 4,
     TryAgain = 5,
+    TimedOut = 6,
+    InvalidArgument = 7,
+    Cancelled = 8,
+    LimitReached = 9,
+    SessionClosed = 10,
+    MessageTooLarge = 11,
+    AlreadyExists = 12,
+    OutOfMemory = 13,
     Unknown = 0xffff,
 }
 
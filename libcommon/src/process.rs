@@ -0,0 +1,17 @@
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+// Why a process stopped running.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, TryFromPrimitive, IntoPrimitive)]
+pub enum ExitReason {
+    Exited = 0,
+    PageFault = 1,
+    UndefinedInstruction = 2,
+    AlignmentFault = 3,
+    DivideError = 4,
+    GeneralProtection = 5,
+    Breakpoint = 6,
+    OtherFault = 7,
+    // Killed by another process.
+    Terminated = 8,
+}
@@ -22,6 +22,7 @@ pub struct MemoryRegion {
 pub enum SystemInfoType {
     MemoryRegion = 0,
     Platform = 1,
+    FirmwareTable = 2,
 }
 
 #[repr(C)]
@@ -33,9 +34,18 @@ pub enum Platform {
     Raspi4,
 }
 
+// Physical addresses of the tables firmware handed to the kernel.
+#[repr(C)]
+#[derive(Debug)]
+pub enum FirmwareTable {
+    AcpiRsdp(usize),
+    DeviceTree(usize),
+}
+
 #[repr(C)]
 pub enum SystemInfo {
     None,
     MemoryRegion(MemoryRegion),
     Platform(Platform),
+    FirmwareTable(FirmwareTable),
 }
@@ -1,21 +1,40 @@
-use crate::os_error::{OSError, OSResult, ResultCode, RESULT_OK};
+use crate::ipc::IPCContext;
+use crate::os_error::{Module, OSError, OSResult, Reason, ResultCode, RESULT_OK};
 use common::ipc::*;
 use core::convert::TryInto;
 
 #[thread_local]
-pub static mut IPC_BUFFER: [u8; 128] = [0; 128];
+pub static mut IPC_BUFFER: [u8; IPC_BUFFER_SIZE] = [0; IPC_BUFFER_SIZE];
 
 pub struct IPCMessage<'a> {
     pub header: IPCHeader,
     pub read_offset: usize,
     pub write_offset: usize,
+    // Reads stop at the end of the message, writes at the end of the buffer.
+    pub read_limit: usize,
+    // Set when something didn't fit. Writes that didn't fit are dropped.
+    pub overflowed: bool,
     pub current_translate: usize,
     pub translate_entries: [TranslateEntry; MAX_TRANSLATE],
     pub buffer: &'a mut [u8],
 }
 
+// Writes a reply into buffer, ready to be sent with ipc_reply. Fails if it didn't fit.
+pub fn write_reply<T: IPCValue>(buffer: &mut [u8], res: T) -> OSResult<()> {
+    let mut reply_msg = IPCMessage::new(buffer);
+    reply_msg.write(res);
+    reply_msg.write_translates();
+    reply_msg.write_header_for(0);
+    reply_msg.check()
+}
+
+// What reads fail with when the message doesn't hold what it should.
+pub fn malformed_message() -> OSError {
+    OSError::new(Module::LibProcess, Reason::InvalidArgument)
+}
+
 pub trait IPCValue {
-    fn read(_msg: &mut IPCMessage) -> Self
+    fn read(_msg: &mut IPCMessage) -> OSResult<Self>
     where
         Self: Sized,
     {
@@ -29,24 +48,36 @@ pub trait IPCValue {
 
 impl IPCMessage<'_> {
     pub fn new(buffer: &mut [u8]) -> IPCMessage {
-        // sizeof(packed header) == 4
+        let read_limit = buffer.len();
         IPCMessage {
             header: IPCHeader {
                 id: 0,
                 size: 0,
                 translate_count: 0,
+                flags: 0,
             },
-            read_offset: 4,
-            write_offset: 4,
+            read_offset: IPC_HEADER_SIZE,
+            write_offset: IPC_HEADER_SIZE,
+            read_limit: read_limit,
+            overflowed: false,
             current_translate: 0,
             translate_entries: [TranslateEntry::None; MAX_TRANSLATE],
             buffer: buffer,
         }
     }
 
-    pub fn read_header(&mut self) {
-        let packed = u32::from_le_bytes(self.buffer[0..4].try_into().unwrap());
-        self.header = IPCHeader::unpack(packed);
+    pub fn read_header(&mut self) -> OSResult<()> {
+        match IPCHeader::read_from(self.buffer) {
+            Some(header) if header.used_length() <= self.buffer.len() => {
+                self.read_limit = header.size;
+                self.header = header;
+                Ok(())
+            }
+            _ => {
+                self.read_limit = IPC_HEADER_SIZE;
+                Err(malformed_message())
+            }
+        }
     }
 
     pub fn write_header_for(&mut self, method_id: u32) {
@@ -54,160 +85,171 @@ impl IPCMessage<'_> {
             id: method_id,
             size: self.write_offset,
             translate_count: self.current_translate,
+            flags: 0,
         };
-        let packed = IPCHeader::pack(&self.header);
+        IPCHeader::write_to(self.buffer, &self.header);
+    }
 
-        self.buffer[0..4].copy_from_slice(&u32::to_le_bytes(packed));
+    // Only meaningful on a message we received.
+    pub fn context(&self) -> IPCContext {
+        let (sender_process_id, badge) = IPCHeader::read_sender(self.buffer);
+        IPCContext {
+            sender_process_id,
+            badge,
+        }
     }
 
     pub fn write_translates(&mut self) {
+        if self.write_offset + self.current_translate * TRANSLATE_ENTRY_SIZE > self.buffer.len() {
+            self.overflowed = true;
+            return;
+        }
+
         for i in 0..self.current_translate {
             let entry = self.translate_entries[i];
-            let off = self.write_offset + i * 16;
-            let buffer = &mut self.buffer[off..off + 16];
+            let off = self.write_offset + i * TRANSLATE_ENTRY_SIZE;
+            let buffer = &mut self.buffer[off..off + TRANSLATE_ENTRY_SIZE];
             TranslateEntry::write(buffer.try_into().unwrap(), entry);
         }
     }
 
+    // Nothing to read if read_header failed, the translate count is still 0.
     pub fn read_translates(&mut self) {
         for i in 0..self.header.translate_count {
-            let off = self.header.size + i * 16;
-            let buffer = &self.buffer[off..off + 16];
+            let off = self.header.size + i * TRANSLATE_ENTRY_SIZE;
+            let buffer = &self.buffer[off..off + TRANSLATE_ENTRY_SIZE];
             self.translate_entries[i] = TranslateEntry::read(buffer.try_into().unwrap());
         }
     }
 
-    pub fn read<T: IPCValue>(&mut self) -> T {
+    // Fails if anything didn't fit in the message.
+    pub fn check(&self) -> OSResult<()> {
+        if self.overflowed {
+            Err(OSError::new(Module::LibProcess, Reason::MessageTooLarge))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn read<T: IPCValue>(&mut self) -> OSResult<T> {
         T::read(self)
     }
 
     pub fn write<T: IPCValue>(&mut self, a: T) {
         T::write(self, &a)
     }
+
+    // Fails if the message is shorter than that.
+    pub fn read_bytes(&mut self, length: usize) -> OSResult<&[u8]> {
+        match self.read_offset.checked_add(length) {
+            Some(end) if end <= self.read_limit => {
+                self.read_offset = end;
+                Ok(&self.buffer[end - length..end])
+            }
+            _ => Err(malformed_message()),
+        }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        let end = self.write_offset + bytes.len();
+        if self.overflowed || end > self.buffer.len() {
+            self.overflowed = true;
+            return;
+        }
+
+        self.buffer[self.write_offset..end].copy_from_slice(bytes);
+        self.write_offset = end;
+    }
+
+    fn read_array<const N: usize>(&mut self) -> OSResult<[u8; N]> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
 }
 
 impl IPCValue for u64 {
-    fn read(msg: &mut IPCMessage) -> u64 {
-        let val = u64::from_le_bytes(
-            msg.buffer[msg.read_offset..msg.read_offset + 8]
-                .try_into()
-                .unwrap(),
-        );
-        msg.read_offset += 8;
-        val
+    fn read(msg: &mut IPCMessage) -> OSResult<u64> {
+        Ok(u64::from_le_bytes(msg.read_array()?))
     }
 
     fn write(msg: &mut IPCMessage, val: &u64) {
-        msg.buffer[msg.write_offset..msg.write_offset + 8].copy_from_slice(&u64::to_le_bytes(*val));
-        msg.write_offset += 8;
+        msg.write_bytes(&u64::to_le_bytes(*val));
     }
 }
 
 impl IPCValue for u32 {
-    fn read(msg: &mut IPCMessage) -> u32 {
-        let val = u32::from_le_bytes(
-            msg.buffer[msg.read_offset..msg.read_offset + 4]
-                .try_into()
-                .unwrap(),
-        );
-        msg.read_offset += 4;
-        val
+    fn read(msg: &mut IPCMessage) -> OSResult<u32> {
+        Ok(u32::from_le_bytes(msg.read_array()?))
     }
 
     fn write(msg: &mut IPCMessage, val: &u32) {
-        msg.buffer[msg.write_offset..msg.write_offset + 4].copy_from_slice(&u32::to_le_bytes(*val));
-        msg.write_offset += 4;
+        msg.write_bytes(&u32::to_le_bytes(*val));
     }
 }
 
 impl IPCValue for u16 {
-    fn read(msg: &mut IPCMessage) -> u16 {
-        let val = u16::from_le_bytes(
-            msg.buffer[msg.read_offset..msg.read_offset + 2]
-                .try_into()
-                .unwrap(),
-        );
-        msg.read_offset += 2;
-        val
+    fn read(msg: &mut IPCMessage) -> OSResult<u16> {
+        Ok(u16::from_le_bytes(msg.read_array()?))
     }
 
     fn write(msg: &mut IPCMessage, val: &u16) {
-        msg.buffer[msg.write_offset..msg.write_offset + 2].copy_from_slice(&u16::to_le_bytes(*val));
-        msg.write_offset += 2;
+        msg.write_bytes(&u16::to_le_bytes(*val));
     }
 }
 
 impl IPCValue for u8 {
-    fn read(msg: &mut IPCMessage) -> u8 {
-        let val = msg.buffer[msg.read_offset];
-        msg.read_offset += 1;
-        val
+    fn read(msg: &mut IPCMessage) -> OSResult<u8> {
+        Ok(u8::from_le_bytes(msg.read_array()?))
     }
 
     fn write(msg: &mut IPCMessage, val: &u8) {
-        msg.buffer[msg.write_offset] = *val;
-        msg.write_offset += 1;
+        msg.write_bytes(&[*val]);
     }
 }
 
 // TODO: sizeof(usize)=4?
 impl IPCValue for usize {
-    fn read(msg: &mut IPCMessage) -> usize {
-        let val = u64::from_le_bytes(
-            msg.buffer[msg.read_offset..msg.read_offset + 8]
-                .try_into()
-                .unwrap(),
-        );
-        msg.read_offset += 8;
-        val as usize
+    fn read(msg: &mut IPCMessage) -> OSResult<usize> {
+        Ok(u64::from_le_bytes(msg.read_array()?) as usize)
     }
 
     fn write(msg: &mut IPCMessage, val: &usize) {
-        msg.buffer[msg.write_offset..msg.write_offset + 8]
-            .copy_from_slice(&u64::to_le_bytes(*val as u64));
-        msg.write_offset += 8;
+        msg.write_bytes(&u64::to_le_bytes(*val as u64));
     }
 }
 
 impl IPCValue for bool {
-    fn read(msg: &mut IPCMessage) -> bool {
-        let val = msg.buffer[msg.read_offset];
-        msg.read_offset += 1;
-        val != 0
+    fn read(msg: &mut IPCMessage) -> OSResult<bool> {
+        Ok(u8::read(msg)? != 0)
     }
 
     fn write(msg: &mut IPCMessage, val: &bool) {
         if *val {
-            msg.buffer[msg.write_offset] = 1;
+            u8::write(msg, &1);
         } else {
-            msg.buffer[msg.write_offset] = 0;
+            u8::write(msg, &0);
         }
-        msg.write_offset += 1;
     }
 }
 
 impl IPCValue for String {
-    fn read(msg: &mut IPCMessage) -> String {
-        let length = usize::read(msg);
-        let bytes: Vec<u8> = msg.buffer[msg.read_offset..msg.read_offset + length].to_vec();
-        msg.read_offset += length;
+    fn read(msg: &mut IPCMessage) -> OSResult<String> {
+        let length = usize::read(msg)?;
+        let bytes: Vec<u8> = msg.read_bytes(length)?.to_vec();
 
-        String::from_utf8(bytes).unwrap()
+        String::from_utf8(bytes).map_err(|_| malformed_message())
     }
 
     fn write(msg: &mut IPCMessage, val: &String) {
         let bytes = val.as_bytes();
         let length = bytes.len();
         usize::write(msg, &length);
-
-        msg.buffer[msg.write_offset..msg.write_offset + length].copy_from_slice(bytes);
-        msg.write_offset += length;
+        msg.write_bytes(bytes);
     }
 }
 
 impl IPCValue for ResultCode {
-    fn read(msg: &mut IPCMessage) -> ResultCode {
-        ResultCode(u32::read(msg))
+    fn read(msg: &mut IPCMessage) -> OSResult<ResultCode> {
+        Ok(ResultCode(u32::read(msg)?))
     }
 
     fn write(msg: &mut IPCMessage, val: &ResultCode) {
@@ -216,8 +258,8 @@ impl IPCValue for ResultCode {
 }
 
 impl IPCValue for OSError {
-    fn read(msg: &mut IPCMessage) -> OSError {
-        OSError::from_result_code(ResultCode::read(msg))
+    fn read(msg: &mut IPCMessage) -> OSResult<OSError> {
+        Ok(OSError::from_result_code(ResultCode::read(msg)?))
     }
 
     fn write(msg: &mut IPCMessage, val: &OSError) {
@@ -226,59 +268,57 @@ impl IPCValue for OSError {
 }
 
 impl IPCValue for TranslateMoveHandle {
-    fn read(msg: &mut IPCMessage) -> TranslateMoveHandle {
-        if let TranslateEntry::MoveHandle(handle) = msg.translate_entries[msg.current_translate] {
-            msg.current_translate += 1;
-            TranslateMoveHandle(handle)
-        } else {
-            println!(
-                "{:?} of {:?}",
-                msg.current_translate, msg.header.translate_count
-            );
-            panic!(
-                "Invalid translate! Expected Move, got {:?}",
-                msg.translate_entries[msg.current_translate]
-            );
+    fn read(msg: &mut IPCMessage) -> OSResult<TranslateMoveHandle> {
+        match msg.translate_entries.get(msg.current_translate) {
+            Some(TranslateEntry::MoveHandle(handle)) => {
+                msg.current_translate += 1;
+                Ok(TranslateMoveHandle(*handle))
+            }
+            _ => Err(malformed_message()),
         }
     }
 
     fn write(msg: &mut IPCMessage, value: &TranslateMoveHandle) {
+        if msg.current_translate == MAX_TRANSLATE {
+            msg.overflowed = true;
+            return;
+        }
+
         msg.translate_entries[msg.current_translate] = TranslateEntry::MoveHandle(value.0);
         msg.current_translate += 1;
     }
 }
 
 impl IPCValue for TranslateCopyHandle {
-    fn read(msg: &mut IPCMessage) -> TranslateCopyHandle {
-        if let TranslateEntry::CopyHandle(handle) = msg.translate_entries[msg.current_translate] {
-            msg.current_translate += 1;
-            TranslateCopyHandle(handle)
-        } else {
-            println!(
-                "{:?} of {:?}",
-                msg.current_translate, msg.header.translate_count
-            );
-            panic!(
-                "Invalid translate! Expected Copy, got {:?}",
-                msg.translate_entries[msg.current_translate]
-            );
+    fn read(msg: &mut IPCMessage) -> OSResult<TranslateCopyHandle> {
+        match msg.translate_entries.get(msg.current_translate) {
+            Some(TranslateEntry::CopyHandle(handle)) => {
+                msg.current_translate += 1;
+                Ok(TranslateCopyHandle(*handle))
+            }
+            _ => Err(malformed_message()),
         }
     }
 
     fn write(msg: &mut IPCMessage, value: &TranslateCopyHandle) {
+        if msg.current_translate == MAX_TRANSLATE {
+            msg.overflowed = true;
+            return;
+        }
+
         msg.translate_entries[msg.current_translate] = TranslateEntry::CopyHandle(value.0);
         msg.current_translate += 1;
     }
 }
 
 impl<T: IPCValue> IPCValue for OSResult<T> {
-    fn read(msg: &mut IPCMessage) -> OSResult<T> {
+    fn read(msg: &mut IPCMessage) -> OSResult<OSResult<T>> {
         // read error code
-        let res = ResultCode::read(msg);
+        let res = ResultCode::read(msg)?;
         if res == RESULT_OK {
-            Ok(T::read(msg))
+            Ok(Ok(T::read(msg)?))
         } else {
-            Err(OSError::from_result_code(res))
+            Ok(Err(OSError::from_result_code(res)))
         }
     }
 
@@ -295,13 +335,12 @@ impl<T: IPCValue> IPCValue for OSResult<T> {
 }
 
 impl<T: IPCValue> IPCValue for Option<T> {
-    fn read(msg: &mut IPCMessage) -> Option<T> {
-        // read error code
-        let present = bool::read(msg);
+    fn read(msg: &mut IPCMessage) -> OSResult<Option<T>> {
+        let present = bool::read(msg)?;
         if present {
-            Some(T::read(msg))
+            Ok(Some(T::read(msg)?))
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -317,7 +356,9 @@ impl<T: IPCValue> IPCValue for Option<T> {
 }
 
 impl IPCValue for () {
-    fn read(_msg: &mut IPCMessage) {}
+    fn read(_msg: &mut IPCMessage) -> OSResult<()> {
+        Ok(())
+    }
 
     fn write(_msg: &mut IPCMessage, _: &()) {}
 }
@@ -326,8 +367,8 @@ impl<T> IPCValue for (T,)
 where
     T: IPCValue,
 {
-    fn read(msg: &mut IPCMessage) -> (T,) {
-        (T::read(msg),)
+    fn read(msg: &mut IPCMessage) -> OSResult<(T,)> {
+        Ok((T::read(msg)?,))
     }
 
     fn write(msg: &mut IPCMessage, val: &(T,)) {
@@ -340,8 +381,8 @@ where
     T: IPCValue,
     U: IPCValue,
 {
-    fn read(msg: &mut IPCMessage) -> (T, U) {
-        (T::read(msg), U::read(msg))
+    fn read(msg: &mut IPCMessage) -> OSResult<(T, U)> {
+        Ok((T::read(msg)?, U::read(msg)?))
     }
 
     fn write(msg: &mut IPCMessage, val: &(T, U)) {
@@ -354,15 +395,16 @@ impl<T> IPCValue for Vec<T>
 where
     T: IPCValue,
 {
-    fn read(msg: &mut IPCMessage) -> Vec<T> {
-        let length = usize::read(msg);
+    fn read(msg: &mut IPCMessage) -> OSResult<Vec<T>> {
+        let length = usize::read(msg)?;
 
-        let mut new_vec = Vec::with_capacity(length);
+        // Don't trust the length, it came from the other side.
+        let mut new_vec = Vec::with_capacity(core::cmp::min(length, msg.read_limit));
         for _ in 0..length {
-            new_vec.push(T::read(msg))
+            new_vec.push(T::read(msg)?)
         }
 
-        new_vec
+        Ok(new_vec)
     }
 
     fn write(msg: &mut IPCMessage, value: &Vec<T>) {
@@ -4,3 +4,42 @@ pub mod pcie;
 pub mod sm;
 
 pub use common::ipc::*;
+use common::Handle;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+static SESSION_LOCKS: Mutex<BTreeMap<u32, Arc<Mutex<()>>>> = Mutex::new(BTreeMap::new());
+
+// The kernel only keeps track of one request at a time on each session, so threads sharing a handle have to take turns.
+pub fn session_lock(handle: Handle) -> Arc<Mutex<()>> {
+    SESSION_LOCKS
+        .lock()
+        .unwrap()
+        .entry(handle.0)
+        .or_default()
+        .clone()
+}
+
+// Called when the handle is closed, before its number can be handed out again.
+pub(crate) fn forget_session_lock(handle: Handle) {
+    SESSION_LOCKS.lock().unwrap().remove(&handle.0);
+}
+
+// What ipc_receive woke up for, and the index of the handle that woke it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Received {
+    // A message on a session, or a new connection on a port.
+    Message(usize),
+    // The client closed its end of the session, so the server should close its end too.
+    SessionClosed(usize),
+    // From ipc_reply_and_receive: the reply couldn't be sent, so nothing was received. The session is most likely gone.
+    ReplyFailed,
+}
+
+// Who sent a message, as told by the kernel. Server methods can ask for this with with_context in their IPC definition.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IPCContext {
+    pub sender_process_id: usize,
+    // Whatever the server picked when it accepted the session, or 0.
+    pub badge: u64,
+}
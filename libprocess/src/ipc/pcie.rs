@@ -1,38 +1,8 @@
+use crate::os_error::OSResult;
 use common::ipc::*;
 use common::Handle;
 use spin::Mutex;
 
-#[derive(Copy, Clone, Default, Debug)]
-pub struct PCIDeviceInfo {
-    pub bus: u8,
-    pub device: u8,
-    pub vendor_id: u16,
-    pub device_id: u16,
-}
-
-use crate::os_error::OSResult;
-//use common::ipc::TranslateMoveHandle;
-use crate::ipc::message::IPCMessage;
-use crate::ipc::message::IPCValue;
-impl IPCValue for PCIDeviceInfo {
-    fn read(msg: &mut IPCMessage) -> PCIDeviceInfo {
-        PCIDeviceInfo {
-            bus: u8::read(msg),
-            device: u8::read(msg),
-            vendor_id: u16::read(msg),
-            device_id: u16::read(msg),
-        }
-    }
-
-    fn write(msg: &mut IPCMessage, val: &PCIDeviceInfo) {
-        u8::write(msg, &val.bus);
-        u8::write(msg, &val.device);
-
-        u16::write(msg, &val.vendor_id);
-        u16::write(msg, &val.device_id);
-    }
-}
-
 static PCIE_HANDLE: Mutex<Option<Handle>> = Mutex::new(None);
 
 fn get_handle_for_pcie() -> Handle {
@@ -1,4 +1,7 @@
+use crate::ipc::Received;
+use crate::os_error::Reason;
 use crate::syscalls;
+use common::ipc::IPC_BUFFER_SIZE;
 use common::Handle;
 use std::collections::HashMap;
 use std::sync::{Arc, MutexGuard};
@@ -33,7 +36,9 @@ pub struct ServerImpl {
     handles: Vec<Handle>,
     should_stop: bool,
     sessions: HashMap<Handle, Arc<dyn IPCSession>>,
-    new_session_event: Handle
+    new_session_event: Handle,
+    // Every accepted session gets its own badge, so methods can tell clients apart.
+    next_badge: u64,
 }
 
 impl std::fmt::Debug for ServerImpl {
@@ -50,7 +55,8 @@ impl ServerImpl {
             handles: vec![port, new_session_event],
             should_stop: false,
             sessions: HashMap::new(),
-            new_session_event: new_session_event
+            new_session_event: new_session_event,
+            next_badge: 1,
         }
     }
 
@@ -66,26 +72,75 @@ pub trait IPCServer {
     fn accept_main_session_in_trait(self: &Arc<Self>) -> Arc<dyn IPCSession>;
 
     fn process_forever(self: Arc<Self>) {
-        loop {
-            let mut ipc_buffer: [u8; 128] = [0; 128];
+        let mut ipc_buffer: [u8; IPC_BUFFER_SIZE] = [0; IPC_BUFFER_SIZE];
+        // Set when a request was handled synchronously, and its reply is waiting in ipc_buffer.
+        let mut pending_reply: Option<Handle> = None;
 
+        loop {
             let server = self.get_server_impl();
+            let reply_handle = pending_reply.take();
             /* ugh i hate this but w/e */
-            let (index, mut ipc_buffer) = tokio::task::block_in_place(|| {
+            let received = tokio::task::block_in_place(|| {
                 let copied_handles = server.handles.clone();
                 drop(server);
 
-                let i = syscalls::ipc_receive(&copied_handles, &mut ipc_buffer).unwrap();
-                (i, ipc_buffer)
+                // Send any reply on the way into receive, it saves a syscall.
+                match reply_handle {
+                    Some(reply_handle) => syscalls::ipc_reply_and_receive(
+                        reply_handle,
+                        &copied_handles,
+                        &mut ipc_buffer,
+                    ),
+                    None => syscalls::ipc_receive(&copied_handles, &mut ipc_buffer),
+                }
             });
 
+            let index = match received {
+                Ok(Received::Message(index)) => index,
+                // The session we were replying on is dead, drop it like a closed one.
+                Ok(Received::ReplyFailed) => {
+                    let handle = reply_handle.unwrap();
+                    let mut server = self.get_server_impl();
+                    if let Some(index) = server.handles.iter().position(|h| *h == handle) {
+                        server.handles.remove(index);
+                    }
+                    server.sessions.remove(&handle);
+                    drop(server);
+
+                    let _ = syscalls::close_handle(handle);
+                    continue;
+                }
+                // Something else got to whatever woke us first, go back to waiting.
+                Err(err) if matches!(err.reason(), Reason::TryAgain) => continue,
+                Err(err) => panic!("ipc_receive failed: {:?}", err),
+                Ok(Received::SessionClosed(index)) => {
+                    // the client went away, forget about the session
+                    let mut server = self.get_server_impl();
+                    let handle = server.handles.remove(index);
+                    server.sessions.remove(&handle);
+                    drop(server);
+
+                    syscalls::close_handle(handle).unwrap();
+                    continue;
+                }
+            };
+
             let mut server = self.get_server_impl();
             if index == 0 {
                 // server handle is signalled!
-                let new_session = syscalls::ipc_accept(server.handles[0]).unwrap();
-                let session = self.accept_main_session_in_trait();
-                server.sessions.insert(new_session, session);
-                server.handles.push(new_session);
+                let badge = server.next_badge;
+                match syscalls::ipc_accept(server.handles[0], badge) {
+                    Ok(new_session) => {
+                        // Only sessions that were actually accepted use up a badge.
+                        server.next_badge += 1;
+                        let session = self.accept_main_session_in_trait();
+                        server.sessions.insert(new_session, session);
+                        server.handles.push(new_session);
+                    }
+                    // The connection we were woken for was already accepted.
+                    Err(err) if matches!(err.reason(), Reason::TryAgain) => {}
+                    Err(err) => panic!("ipc_accept failed: {:?}", err),
+                }
                 drop(server);
             } else if index == 1 {
                 // new session, do nothing
@@ -99,7 +154,9 @@ pub trait IPCServer {
                 let session = server.sessions[&handle].clone();
 
                 drop(server);
-                session.process(handle, &mut ipc_buffer);
+                if session.process(handle, &mut ipc_buffer) {
+                    pending_reply = Some(handle);
+                }
             }
 
             let server = self.get_server_impl();
@@ -107,9 +164,15 @@ pub trait IPCServer {
                 break;
             }
         }
+
+        if let Some(reply_handle) = pending_reply {
+            let _ = syscalls::ipc_reply(reply_handle, &mut ipc_buffer);
+        }
     }
 }
 
 pub trait IPCSession: Send + Sync {
-    fn process(self: Arc<Self>, h: Handle, ipc_buffer: &mut [u8]);
+    // Returns true if the reply was written to ipc_buffer and should be sent to h.
+    // Otherwise whatever handles the request replies by itself later.
+    fn process(self: Arc<Self>, h: Handle, ipc_buffer: &mut [u8]) -> bool;
 }
@@ -21,6 +21,10 @@ impl OSError {
     pub fn to_result_code(e: &OSError) -> ResultCode {
         CommonError::to_result_code(&e.common)
     }
+
+    pub fn reason(&self) -> Reason {
+        self.common.reason
+    }
 }
 
 impl From<CommonError> for OSError {
@@ -0,0 +1,648 @@
+use crate::ipc::Received;
+use crate::os_error::{Module, OSError, Reason, ResultCode, RESULT_OK};
+use common::ipc::{IPC_BUFFER_SIZE, REPLY_FAILED_INDEX};
+use common::system_info::*;
+use common::{Handle, HandleRights, INVALID_HANDLE};
+use common::{MapType, PagePermission};
+use core::cmp::min;
+use core::convert::TryFrom;
+use core::sync::atomic::AtomicU32;
+
+// The syscall_ functions, and the assembly stubs at the bottom, come from syscall_definitions/syscalls.toml.
+#[cfg(target_os = "francium")]
+include!(concat!(env!("OUT_DIR"), "/syscalls_impl.rs"));
+#[cfg(not(target_os = "francium"))]
+include!(concat!(env!("OUT_DIR"), "/syscalls_emulated_impl.rs"));
+#[cfg(not(target_os = "francium"))]
+mod emulated;
+#[cfg(not(target_os = "francium"))]
+use emulated::*;
+
+pub fn print(s: &str) {
+    unsafe {
+        syscall_debug_output(s.as_bytes().as_ptr(), s.len());
+    }
+}
+
+pub fn make_tag(s: &str) -> u64 {
+    let tag_bytes = s.as_bytes();
+    let length = min(8, tag_bytes.len());
+    let mut tag_bytes_padded: [u8; 8] = [0; 8];
+    tag_bytes_padded[0..length].copy_from_slice(tag_bytes);
+
+    u64::from_be_bytes(tag_bytes_padded)
+}
+
+pub fn create_port(s: &str) -> Result<Handle, OSError> {
+    let mut handle_out = INVALID_HANDLE;
+    unsafe {
+        let res = syscall_create_port(make_tag(s), &mut handle_out);
+        if res == RESULT_OK {
+            Ok(handle_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+// Connections to a port are accepted in the order they were made.
+pub fn connect_to_named_port(s: &str) -> Result<Handle, OSError> {
+    let mut handle_out = INVALID_HANDLE;
+    unsafe {
+        let res = syscall_connect_to_named_port(make_tag(s), &mut handle_out);
+        if res == RESULT_OK {
+            Ok(handle_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn connect_to_port_handle(h: Handle) -> Result<Handle, OSError> {
+    let mut handle_out = INVALID_HANDLE;
+    unsafe {
+        let res = syscall_connect_to_port_handle(h.0, &mut handle_out);
+        if res == RESULT_OK {
+            Ok(handle_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+// Once this many connections are waiting to be accepted, connecting fails with TryAgain. 0 turns everyone away.
+pub fn set_port_backlog(port: Handle, backlog: usize) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_set_port_backlog(port, backlog);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+// Turns down the next connection waiting on the port, instead of accepting it.
+pub fn ipc_refuse(port: Handle) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_ipc_refuse(port);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn close_handle(h: Handle) -> Result<(), OSError> {
+    crate::ipc::forget_session_lock(h);
+    unsafe {
+        let res = syscall_close_handle(h);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn exit_process() -> ! {
+    exit_process_with_code(0);
+}
+
+pub fn exit_process_with_code(exit_code: i32) -> ! {
+    unsafe {
+        syscall_exit_process(exit_code);
+    }
+}
+
+// Requests on a session are served in the order they were made, so no client can be starved by busier ones.
+pub fn ipc_request(
+    session_handle: Handle,
+    ipc_buffer: &mut [u8; IPC_BUFFER_SIZE],
+) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_ipc_request(session_handle, ipc_buffer.as_mut_ptr());
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn ipc_reply(
+    session_handle: Handle,
+    ipc_buffer: &mut [u8; IPC_BUFFER_SIZE],
+) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_ipc_reply(session_handle, ipc_buffer.as_mut_ptr());
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+// Sends the message in ipc_buffer without waiting for the server. Fails with TryAgain if the session has too many waiting.
+// The server gets notifications and requests in the order they were sent.
+pub fn ipc_notify(
+    session_handle: Handle,
+    ipc_buffer: &mut [u8; IPC_BUFFER_SIZE],
+) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_ipc_notify(session_handle, ipc_buffer.as_mut_ptr());
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn ipc_receive(
+    sessions: &[Handle],
+    ipc_buffer: &mut [u8; IPC_BUFFER_SIZE],
+) -> Result<Received, OSError> {
+    ipc_receive_timeout(sessions, ipc_buffer, WAIT_INFINITE)
+}
+
+pub fn ipc_receive_timeout(
+    sessions: &[Handle],
+    ipc_buffer: &mut [u8; IPC_BUFFER_SIZE],
+    timeout_ns: u64,
+) -> Result<Received, OSError> {
+    unsafe {
+        let mut index_out: usize = 0;
+        let res = syscall_ipc_receive(
+            sessions.as_ptr(),
+            sessions.len(),
+            ipc_buffer.as_mut_ptr(),
+            timeout_ns,
+            &mut index_out,
+        );
+        if res == RESULT_OK {
+            Ok(Received::Message(index_out))
+        } else if res == ResultCode::new(Module::Kernel, Reason::SessionClosed) {
+            Ok(Received::SessionClosed(index_out))
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+// Sends the reply in ipc_buffer on reply_session_handle, then receives the next message into it.
+pub fn ipc_reply_and_receive(
+    reply_session_handle: Handle,
+    sessions: &[Handle],
+    ipc_buffer: &mut [u8; IPC_BUFFER_SIZE],
+) -> Result<Received, OSError> {
+    unsafe {
+        let mut index_out: usize = 0;
+        let res = syscall_ipc_reply_and_receive(
+            reply_session_handle,
+            sessions.as_ptr(),
+            sessions.len(),
+            ipc_buffer.as_mut_ptr(),
+            WAIT_INFINITE,
+            &mut index_out,
+        );
+        if res == RESULT_OK {
+            Ok(Received::Message(index_out))
+        } else if index_out == REPLY_FAILED_INDEX {
+            Ok(Received::ReplyFailed)
+        } else if res == ResultCode::new(Module::Kernel, Reason::SessionClosed) {
+            Ok(Received::SessionClosed(index_out))
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+// The badge is handed back with every message received on the new session.
+pub fn ipc_accept(session_handle: Handle, badge: u64) -> Result<Handle, OSError> {
+    unsafe {
+        let mut handle_out: Handle = INVALID_HANDLE;
+        let res = syscall_ipc_accept(session_handle, badge, &mut handle_out);
+        if res == RESULT_OK {
+            Ok(handle_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn get_process_id() -> u64 {
+    unsafe { syscall_get_process_id() }
+}
+
+pub fn map_memory(
+    address: usize,
+    length: usize,
+    permission: PagePermission,
+) -> Result<usize, OSError> {
+    unsafe {
+        let mut address_out: usize = 0;
+        let res = syscall_map_memory(address, length, permission.bits(), &mut address_out);
+        if res == RESULT_OK {
+            Ok(address_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn sleep_ns(ns: u64) {
+    unsafe {
+        syscall_sleep_ns(ns);
+    }
+}
+
+pub fn get_thread_pointer() -> usize {
+    unsafe { syscall_get_thread_pointer() }
+}
+
+pub fn set_thread_pointer(addr: usize) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_set_thread_pointer(addr);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn get_thread_id() -> u64 {
+    unsafe { syscall_get_thread_id() }
+}
+
+pub fn map_device_memory(
+    phys_addr: usize,
+    virt_addr: usize,
+    length: usize,
+    ty: MapType,
+    permission: PagePermission,
+) -> Result<usize, OSError> {
+    unsafe {
+        let mut address_out: usize = 0;
+        let res = syscall_map_device_memory(
+            phys_addr,
+            virt_addr,
+            length,
+            ty as usize,
+            permission.bits(),
+            &mut address_out,
+        );
+        if res == RESULT_OK {
+            Ok(address_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn get_system_info(ty: SystemInfoType, index: usize) -> Result<SystemInfo, OSError> {
+    unsafe {
+        let mut output: SystemInfo = SystemInfo::None;
+        let res = syscall_get_system_info(ty as usize, index, &mut output);
+        if res == RESULT_OK {
+            Ok(output)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn get_system_tick() -> u64 {
+    unsafe { syscall_get_system_tick() }
+}
+
+pub fn query_physical_address(virt: usize) -> Result<usize, OSError> {
+    unsafe {
+        let mut phys_out: usize = 0;
+        let res = syscall_query_physical_address(virt, &mut phys_out);
+        if res == RESULT_OK {
+            Ok(phys_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn create_event() -> Result<Handle, OSError> {
+    unsafe {
+        let mut handle_out: Handle = INVALID_HANDLE;
+        let res = syscall_create_event(&mut handle_out);
+        if res == RESULT_OK {
+            Ok(handle_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn bind_interrupt(handle: Handle, index: usize) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_bind_interrupt(handle, index);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn unbind_interrupt(handle: Handle, index: usize) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_unbind_interrupt(handle, index);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn wait_one(handle: Handle) -> Result<(), OSError> {
+    wait_one_timeout(handle, WAIT_INFINITE)
+}
+
+pub fn wait_one_timeout(handle: Handle, timeout_ns: u64) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_wait_one(handle, timeout_ns);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn signal_event(handle: Handle) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_signal_event(handle);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn clear_event(handle: Handle) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_clear_event(handle);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn wait_many(handles: &[Handle]) -> Result<usize, OSError> {
+    wait_many_timeout(handles, WAIT_INFINITE)
+}
+
+pub fn wait_many_timeout(handles: &[Handle], timeout_ns: u64) -> Result<usize, OSError> {
+    unsafe {
+        let mut index_out: usize = 0;
+        let res = syscall_wait_many(handles.as_ptr(), handles.len(), timeout_ns, &mut index_out);
+        if res == RESULT_OK {
+            Ok(index_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn cancel_wait(thread_id: u64) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_cancel_wait(thread_id);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn create_timer() -> Result<Handle, OSError> {
+    unsafe {
+        let mut handle_out: Handle = INVALID_HANDLE;
+        let res = syscall_create_timer(&mut handle_out);
+        if res == RESULT_OK {
+            Ok(handle_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+// A period of 0 makes the timer one-shot. Periods under 100us are refused.
+pub fn set_timer(handle: Handle, initial_ns: u64, period_ns: u64) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_set_timer(handle, initial_ns, period_ns);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn cancel_timer(handle: Handle) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_cancel_timer(handle);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn create_session() -> Result<(Handle, Handle), OSError> {
+    unsafe {
+        let mut server_handle: Handle = INVALID_HANDLE;
+        let mut client_handle: Handle = INVALID_HANDLE;
+        let res = syscall_create_session(&mut server_handle, &mut client_handle);
+        if res == RESULT_OK {
+            Ok((server_handle, client_handle))
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub use common::constants::WAIT_INFINITE;
+
+pub fn futex_wait(futex: &AtomicU32, expected: u32, timeout_ns: u64) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_futex_wait(
+            futex as *const AtomicU32 as *const u32,
+            expected,
+            timeout_ns,
+        );
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn futex_wake(futex: &AtomicU32) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_futex_wake(futex as *const AtomicU32 as *const u32);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn futex_wake_count(futex: &AtomicU32, count: usize) -> Result<usize, OSError> {
+    unsafe {
+        let mut woken_out: usize = 0;
+        let res = syscall_futex_wake_count(
+            futex as *const AtomicU32 as *const u32,
+            count,
+            &mut woken_out,
+        );
+        if res == RESULT_OK {
+            Ok(woken_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn futex_requeue(
+    futex: &AtomicU32,
+    wake_count: usize,
+    target: &AtomicU32,
+    requeue_count: usize,
+) -> Result<usize, OSError> {
+    unsafe {
+        let mut count_out: usize = 0;
+        let res = syscall_futex_requeue(
+            futex as *const AtomicU32 as *const u32,
+            wake_count,
+            target as *const AtomicU32 as *const u32,
+            requeue_count,
+            &mut count_out,
+        );
+        if res == RESULT_OK {
+            Ok(count_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+// Creates an empty process. Map memory into it, fill it, then start it.
+pub fn create_process(name: &str) -> Result<Handle, OSError> {
+    unsafe {
+        let mut handle_out: Handle = INVALID_HANDLE;
+        let res = syscall_create_process(name.as_ptr(), name.len(), &mut handle_out);
+        if res == RESULT_OK {
+            Ok(handle_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn map_process_memory(
+    process: Handle,
+    address: usize,
+    length: usize,
+    permission: PagePermission,
+) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_map_process_memory(process, address, length, permission.bits());
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn write_process_memory(process: Handle, address: usize, data: &[u8]) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_write_process_memory(process, address, data.as_ptr(), data.len());
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn start_process(process: Handle, entry_point: usize, stack_top: usize) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_start_process(process, entry_point, stack_top);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub use common::process::ExitReason;
+
+// Returns why the process exited, and its exit code. Fails with TryAgain if it's still running.
+pub fn get_process_exit_info(process: Handle) -> Result<(ExitReason, i32), OSError> {
+    unsafe {
+        let mut info_out: usize = 0;
+        let res = syscall_get_process_exit_info(process, &mut info_out);
+        if res == RESULT_OK {
+            let reason = ExitReason::try_from((info_out >> 32) as u32)
+                .map_err(|_| OSError::new(Module::LibProcess, Reason::Unknown))?;
+            Ok((reason, info_out as u32 as i32))
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+// Kills every thread in the process. Waiters on the process handle see it exit with ExitReason::Terminated.
+pub fn terminate_process(process: Handle) -> Result<(), OSError> {
+    unsafe {
+        let res = syscall_terminate_process(process);
+        if res == RESULT_OK {
+            Ok(())
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+pub fn duplicate_handle(handle: Handle, rights: HandleRights) -> Result<Handle, OSError> {
+    unsafe {
+        let mut handle_out: Handle = INVALID_HANDLE;
+        let res = syscall_duplicate_handle(handle, rights, &mut handle_out);
+        if res == RESULT_OK {
+            Ok(handle_out)
+        } else {
+            Err(OSError::from_result_code(res))
+        }
+    }
+}
+
+#[cfg(target_os = "francium")]
+use core::arch::global_asm;
+#[cfg(all(target_os = "francium", target_arch = "x86_64"))]
+global_asm!(include_str!(concat!(env!("OUT_DIR"), "/x86_64_syscalls.s")));
+
+#[cfg(all(target_os = "francium", target_arch = "aarch64"))]
+global_asm!(include_str!(concat!(
+    env!("OUT_DIR"),
+    "/aarch64_syscalls.s"
+)));
@@ -0,0 +1,52 @@
+// Host versions of the syscalls marked emulated in syscalls.toml, for running libprocess off francium.
+// They have the same signatures as the generated stubs they replace.
+
+use crate::os_error::{ResultCode, RESULT_OK};
+use std::cell::Cell;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+static START: OnceLock<Instant> = OnceLock::new();
+
+thread_local! {
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+    static THREAD_POINTER: Cell<usize> = const { Cell::new(0) };
+}
+
+pub unsafe fn syscall_debug_output(s: *const u8, len: usize) {
+    let bytes = core::slice::from_raw_parts(s, len);
+    let _ = std::io::stdout().write_all(bytes);
+}
+
+pub unsafe fn syscall_exit_process(exit_code: i32) -> ! {
+    std::process::exit(exit_code)
+}
+
+pub unsafe fn syscall_get_process_id() -> u64 {
+    std::process::id() as u64
+}
+
+pub unsafe fn syscall_sleep_ns(ns: u64) {
+    std::thread::sleep(Duration::from_nanos(ns));
+}
+
+pub unsafe fn syscall_get_thread_id() -> u64 {
+    THREAD_ID.with(|id| *id)
+}
+
+// Nanoseconds, like the kernel's, but counted from the first call rather than from boot.
+pub unsafe fn syscall_get_system_tick() -> u64 {
+    START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+pub unsafe fn syscall_get_thread_pointer() -> usize {
+    THREAD_POINTER.with(|tp| tp.get())
+}
+
+pub unsafe fn syscall_set_thread_pointer(addr: usize) -> ResultCode {
+    THREAD_POINTER.with(|tp| tp.set(addr));
+    RESULT_OK
+}
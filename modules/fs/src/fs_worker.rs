@@ -1,6 +1,7 @@
 use std::sync::mpsc;
-use process::os_error::OSResult;
+use process::os_error::{Module, OSError, OSResult, Reason};
 use crate::block_adapter::BlockAdapter;
+use fatfs::{Read, Seek, SeekFrom};
 
 pub struct FSWorkerClient {
     request: mpsc::Sender<FSWorkerRequest>,
@@ -21,7 +22,8 @@ impl FSWorkerClient {
 #[derive(Debug)]
 pub enum FSWorkerRequest {
     Open(String),
-    Read(usize),
+    /* File handle, offset, length */
+    Read(usize, usize, usize),
     Write(usize),
     /* ... */
 }
@@ -30,21 +32,62 @@ pub enum FSWorkerRequest {
 pub enum FSWorkerResponse {
     /* An internal handle to the new file */
     Open(OSResult<usize>),
+    /* Shorter than asked for at the end of the file */
+    Read(OSResult<Vec<u8>>),
 }
 
+pub fn map_fatfs_error(e: fatfs::Error<std::io::Error>) -> OSError {
+    match e {
+        fatfs::Error::NotFound => OSError::new(Module::Fs, Reason::NotFound),
+        _ => OSError::new(Module::Fs, Reason::Unknown),
+    }
+}
 
 type FatFilesystem = fatfs::FileSystem<
     fatfs::StdIoWrapper<BlockAdapter>,
     fatfs::DefaultTimeProvider,
     fatfs::LossyOemCpConverter,
 >;
+
+// fatfs files borrow the filesystem, so we keep the path and open it again for each read.
+fn read_file(fs: &FatFilesystem, path: &str, offset: usize, length: usize) -> OSResult<Vec<u8>> {
+    let mut file = fs.root_dir().open_file(path).map_err(map_fatfs_error)?;
+    file.seek(SeekFrom::Start(offset as u64)).map_err(map_fatfs_error)?;
+
+    let mut buf = vec![0; length];
+    let mut read = 0;
+    while read < length {
+        let n = file.read(&mut buf[read..]).map_err(map_fatfs_error)?;
+        if n == 0 {
+            break;
+        }
+        read += n;
+    }
+
+    buf.truncate(read);
+    Ok(buf)
+}
+
 pub fn fs_worker_thread(request: mpsc::Receiver<FSWorkerRequest>, response: mpsc::Sender<FSWorkerResponse>, fs: FatFilesystem) {
     println!("Hello from fs worker");
+    let mut open_files: Vec<String> = Vec::new();
+
     loop {
         let req = request.recv().unwrap();
         match req {
             FSWorkerRequest::Open(filename) => {
-                response.send(FSWorkerResponse::Open(Ok(0))).unwrap();
+                let res = fs.root_dir().open_file(&filename).map_err(map_fatfs_error).map(|_| {
+                    open_files.push(filename);
+                    open_files.len() - 1
+                });
+                response.send(FSWorkerResponse::Open(res)).unwrap();
+            }
+            FSWorkerRequest::Read(file_handle, offset, length) => {
+                let res = match open_files.get(file_handle) {
+                    Some(path) => read_file(&fs, path, offset, length),
+                    None => Err(OSError::new(Module::Fs, Reason::InvalidHandle)),
+                };
+                response.send(FSWorkerResponse::Read(res)).unwrap();
             }
             _ => {
                 println!("AAAAAAAAAAA");
@@ -52,4 +95,4 @@ pub fn fs_worker_thread(request: mpsc::Receiver<FSWorkerRequest>, response: mpsc
             }
         }
     }
-}
\ No newline at end of file
+}
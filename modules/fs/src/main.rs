@@ -45,12 +45,8 @@ define_session! {
     FSServerStruct
 }
 
-fn map_fatfs_error(e: fatfs::Error<std::io::Error>) -> OSError {
-    match e {
-        fatfs::Error::NotFound => OSError::new(Module::Fs, Reason::NotFound),
-        _ => OSError::new(Module::Fs, Reason::Unknown),
-    }
-}
+// Leaves room for the header and result in a one page reply.
+const MAX_READ_LENGTH: usize = 0x800;
 
 impl FSServerStruct {
     fn accept_main_session(self: &Arc<FSServerStruct>) -> Arc<FSSession> {
@@ -87,9 +83,17 @@ impl FSSession {
 }
 
 impl IFileSession {
-    fn read_file(&self, length: usize) -> OSResult<usize> {
-        println!("Read file");
-        Ok(0)
+    fn read_file(&self, offset: usize, length: usize) -> OSResult<Vec<u8>> {
+        let server = self.get_server();
+        let fs = server.fs_worker.lock().unwrap();
+
+        let length = core::cmp::min(length, MAX_READ_LENGTH);
+        let response = fs.do_request(FSWorkerRequest::Read(self.file_handle, offset, length))?;
+        if let FSWorkerResponse::Read(read) = response {
+            read
+        } else {
+            Err(OSError::from_result_code(ResultCode::new(Module::Fs, Reason::Unknown)))
+        }
     }
 }
 
@@ -99,7 +103,9 @@ async fn main() {
 
     let port = syscalls::create_port("").unwrap();
 
-    sm::register_port(syscalls::make_tag("fs"), TranslateCopyHandle(port)).unwrap();
+    sm::register_port_async(syscalls::make_tag("fs"), TranslateCopyHandle(port))
+        .await
+        .unwrap();
 
     let mut blocks = block_virtio::scan();
     let first_block = blocks.pop().unwrap();
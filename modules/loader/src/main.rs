@@ -1,3 +1,179 @@
+use elf_rs::*;
+use francium_common::align::align_up;
+use francium_common::types::PagePermission;
+use process::ipc;
+use process::os_error::{Module, OSError, OSResult, Reason};
+use process::syscalls;
+use process::Handle;
+
+const PAGE_SIZE: usize = 0x1000;
+
+const USER_STACK_BASE: usize = 0x40000000;
+const USER_STACK_SIZE: usize = 0x4000;
+
+// fs hands back at most this much per read.
+const READ_CHUNK_SIZE: usize = 0x800;
+
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+
+fn invalid_elf() -> OSError {
+    OSError::new(Module::Loader, Reason::InvalidArgument)
+}
+
+// Lays out argc, argv, envp and auxv the same way the kernel does for the processes it loads.
+fn build_initial_stack(argv: &[&str], auxv: &[(usize, usize)]) -> (usize, Vec<u8>) {
+    let argv_size = 8 + (argv.len() + 1) * 8;
+    let env_size = 8;
+    let auxv_size = (auxv.len() + 1) * 16;
+    let strings_len = argv.iter().map(|x| x.len() + 1).sum::<usize>();
+
+    let stack_top = USER_STACK_BASE + USER_STACK_SIZE;
+    let region_size = align_up(argv_size + env_size + auxv_size + strings_len + 8, 16);
+    let base = stack_top - region_size;
+
+    let mut words: Vec<usize> = Vec::new();
+    let mut strings: Vec<u8> = Vec::new();
+    let strings_base = base + argv_size + env_size + auxv_size;
+
+    words.push(argv.len());
+    for arg in argv {
+        words.push(strings_base + strings.len());
+        strings.extend_from_slice(arg.as_bytes());
+        strings.push(0);
+    }
+    words.push(0);
+
+    // No environment.
+    words.push(0);
+
+    for (key, value) in auxv {
+        words.push(*key);
+        words.push(*value);
+    }
+    words.push(AT_NULL);
+    words.push(0);
+
+    let mut image: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    image.extend_from_slice(&strings);
+    image.resize(region_size, 0);
+
+    (base, image)
+}
+
+fn load_elf(name: &str, elf_buf: &[u8]) -> OSResult<Handle> {
+    let e = match Elf::from_bytes(elf_buf) {
+        Ok(Elf::Elf64(e)) => e,
+        _ => return Err(invalid_elf()),
+    };
+
+    let process = syscalls::create_process(name)?;
+
+    // Until it's started, the process only lives as long as our handle.
+    match populate_process(process, name, &e, elf_buf) {
+        Ok(()) => Ok(process),
+        Err(err) => {
+            let _ = syscalls::close_handle(process);
+            Err(err)
+        }
+    }
+}
+
+fn populate_process(process: Handle, name: &str, e: &Elf64, elf_buf: &[u8]) -> OSResult<()> {
+    let mut smallest_base = usize::MAX;
+    let mut mapped_end = 0;
+
+    for ph in e.program_header_iter() {
+        if ph.ph_type() != ProgramType::LOAD {
+            continue;
+        }
+
+        let vaddr = ph.vaddr() as usize;
+        let file_offset = ph.offset() as usize;
+        let file_size = ph.filesz() as usize;
+        let mem_size = ph.memsz() as usize;
+
+        if vaddr < smallest_base {
+            smallest_base = vaddr;
+        }
+
+        // Segments can share a page, which will already be mapped by the segment before.
+        let start = core::cmp::max(vaddr & !(PAGE_SIZE - 1), mapped_end);
+        let end = align_up(vaddr + mem_size, PAGE_SIZE);
+
+        let permission =
+            if (ph.flags() & ProgramHeaderFlags::EXECUTE) == ProgramHeaderFlags::EXECUTE {
+                PagePermission::USER_RWX
+            } else {
+                PagePermission::USER_READ_WRITE
+            };
+
+        if end > start {
+            syscalls::map_process_memory(process, start, end - start, permission)?;
+            mapped_end = end;
+        }
+
+        // The rest of the segment is BSS, and fresh memory is already zeroed.
+        if file_size != 0 {
+            let contents = elf_buf
+                .get(file_offset..file_offset + file_size)
+                .ok_or_else(invalid_elf)?;
+            syscalls::write_process_memory(process, vaddr, contents)?;
+        }
+    }
+
+    syscalls::map_process_memory(
+        process,
+        USER_STACK_BASE,
+        USER_STACK_SIZE,
+        PagePermission::USER_READ_WRITE,
+    )?;
+
+    let auxv = [
+        (
+            AT_PHDR,
+            smallest_base + e.elf_header().program_header_offset() as usize,
+        ),
+        (
+            AT_PHENT,
+            e.elf_header().program_header_entry_size() as usize,
+        ),
+        (AT_PHNUM, e.elf_header().program_header_entry_num() as usize),
+    ];
+    let (stack_pointer, stack_image) = build_initial_stack(&[name], &auxv);
+    syscalls::write_process_memory(process, stack_pointer, &stack_image)?;
+
+    let entry_point = e.elf_header().entry_point() as usize;
+    syscalls::start_process(process, entry_point, stack_pointer)
+}
+
+fn read_file(path: &str) -> OSResult<Vec<u8>> {
+    let file = ipc::fs::open_file(path.to_string())?;
+
+    let mut contents = Vec::new();
+    let res = loop {
+        match ipc::fs::read_file(file.0, contents.len(), READ_CHUNK_SIZE) {
+            Ok(chunk) if chunk.is_empty() => break Ok(contents),
+            Ok(chunk) => contents.extend_from_slice(&chunk),
+            Err(err) => break Err(err),
+        }
+    };
+
+    syscalls::close_handle(file.0)?;
+    res
+}
+
 fn main() {
-    println!("Hello, world!");
+    println!("Hello from loader!");
+
+    for path in std::env::args().skip(1) {
+        match read_file(&path).and_then(|elf_buf| load_elf(&path, &elf_buf)) {
+            Ok(process) => println!("Started {} ({:?})", path, process),
+            Err(err) => println!("Failed to load {}: {:?}", path, err),
+        }
+    }
+
+    syscalls::exit_process();
 }
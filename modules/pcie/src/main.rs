@@ -13,8 +13,9 @@ mod pcie_acpi;
 #[cfg(target_arch = "aarch64")]
 mod pcie_dt;
 
+#[cfg(target_arch = "aarch64")]
+use common::system_info::{FirmwareTable, SystemInfo, SystemInfoType};
 use common::Handle;
-use process::ipc::pcie::PCIDeviceInfo;
 use process::ipc_server::IPCServer;
 use process::os_error::*;
 use process::{define_server, define_session};
@@ -321,8 +322,8 @@ impl PCIESession {
                         syscalls::unbind_interrupt(
                             event_handle.0,
                             func.inner.interrupt_line as usize,
-                        )
-                        .unwrap();
+                        )?;
+                        return Ok(());
                     }
                 }
             }
@@ -344,12 +345,19 @@ async fn main() {
     #[cfg(target_arch = "x86_64")]
     let interrupts = None;
 
+    #[cfg(target_arch = "aarch64")]
+    let dt_addr = match syscalls::get_system_info(SystemInfoType::FirmwareTable, 0) {
+        Ok(SystemInfo::FirmwareTable(FirmwareTable::DeviceTree(addr))) => addr,
+        _ => panic!("Couldn't find the device tree!"),
+    };
     #[cfg(target_arch = "aarch64")]
     let (pcie_buses, io_space_addr, pci_32bit_addr, _pci_64bit_addr, interrupts) =
-        pcie_dt::scan_via_device_tree(0x40000000);
+        pcie_dt::scan_via_device_tree(dt_addr);
 
     let port = syscalls::create_port("").unwrap();
-    sm::register_port(syscalls::make_tag("pcie"), TranslateCopyHandle(port)).unwrap();
+    sm::register_port_async(syscalls::make_tag("pcie"), TranslateCopyHandle(port))
+        .await
+        .unwrap();
 
     let server = Arc::new(PCIEServerStruct {
         __server_impl: Mutex::new(ServerImpl::new(port)),
@@ -5,15 +5,16 @@ use async_broadcast::broadcast;
 
 use process::ipc::*;
 use process::ipc_server::{IPCServer, ServerImpl};
-use process::os_error::OSResult;
+use process::os_error::{Module, OSError, OSResult, Reason};
 use process::syscalls;
-use process::Handle;
 use process::{define_server, define_session};
+use process::{Handle, HandleRights};
 
 include!(concat!(env!("OUT_DIR"), "/sm_server_impl.rs"));
 
 define_server!(SMServerStruct {
-    server_ports: Mutex<HashMap<u64, Handle>>,
+    // The port, and the process that registered it.
+    server_ports: Mutex<HashMap<u64, (Handle, usize)>>,
     server_waiters: Mutex<
         HashMap<
             u64,
@@ -27,6 +28,11 @@ define_server!(SMServerStruct {
 
 define_session!(SMSession {}, SMServerStruct);
 
+// Ports without the right to accept are only signalled once they close.
+fn port_is_closed(port: Handle) -> bool {
+    syscalls::wait_one_timeout(port, 0).is_ok()
+}
+
 impl SMServerStruct {
     fn accept_main_session(self: &Arc<SMServerStruct>) -> Arc<SMSession> {
         Arc::new(SMSession {
@@ -43,7 +49,7 @@ impl SMSession {
                 .lock()
                 .unwrap()
                 .get(&tag)
-                .map(|x| *x)
+                .map(|x| x.0)
         };
 
         let server_port = match server_port {
@@ -72,12 +78,37 @@ impl SMSession {
         Ok(TranslateMoveHandle(client_session))
     }
 
-    async fn register_port(&self, tag: u64, port_handle: TranslateCopyHandle) -> OSResult<()> {
-        self.get_server()
-            .server_ports
-            .lock()
-            .unwrap()
-            .insert(tag, port_handle.0);
+    async fn register_port(
+        &self,
+        ctx: IPCContext,
+        tag: u64,
+        port_handle: TranslateCopyHandle,
+    ) -> OSResult<()> {
+        // We only ever connect to registered ports, so don't hold on to the right to accept on them.
+        // Waiting on what's left tells us when the port closes.
+        let connect_handle = syscalls::duplicate_handle(
+            port_handle.0,
+            HandleRights::CONNECT | HandleRights::TRANSFER | HandleRights::WAIT,
+        )?;
+        syscalls::close_handle(port_handle.0)?;
+
+        // Only whoever registered a service gets to replace it, until its port closes.
+        // A service that crashed and restarted has a new pid, so it can register again once its old port is gone.
+        let old_port = {
+            let server = self.get_server();
+            let mut server_ports = server.server_ports.lock().unwrap();
+            if let Some((old_handle, owner)) = server_ports.get(&tag) {
+                if *owner != ctx.sender_process_id && !port_is_closed(*old_handle) {
+                    syscalls::close_handle(connect_handle)?;
+                    return Err(OSError::new(Module::Sm, Reason::NotAllowed));
+                }
+            }
+            server_ports.insert(tag, (connect_handle, ctx.sender_process_id))
+        };
+
+        if let Some((old_handle, _)) = old_port {
+            syscalls::close_handle(old_handle)?;
+        }
 
         let new_tag = self
             .get_server()
@@ -86,7 +117,7 @@ impl SMSession {
             .unwrap()
             .remove(&tag);
         if let Some((send, _recv)) = new_tag {
-            send.broadcast(port_handle.0).await.unwrap();
+            send.broadcast(connect_handle).await.unwrap();
         }
 
         Ok(())
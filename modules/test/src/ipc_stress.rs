@@ -0,0 +1,154 @@
+// Stress test for the kernel's IPC queues.
+// Lots of client threads share one session (or one port), and the server writes down who it served.
+// Requests and connections are served in the order they were made, so between two turns of a client,
+// every other client can be served at most once. Anything more means someone is being starved.
+
+use process::ipc::message::{write_reply, IPCMessage};
+use process::ipc::{Received, IPC_BUFFER_SIZE};
+use process::syscalls;
+use std::thread;
+
+const CLIENT_COUNT: usize = 8;
+const ROUNDS: usize = 32;
+
+// The most other clients that were served between two turns of the same client.
+fn worst_gap(order: &[usize]) -> usize {
+    let mut last_seen = [None; CLIENT_COUNT];
+    let mut worst = 0;
+
+    for (i, &client) in order.iter().enumerate() {
+        if let Some(last) = last_seen[client] {
+            worst = worst.max(i - last - 1);
+        }
+        last_seen[client] = Some(i);
+    }
+
+    worst
+}
+
+fn check(name: &str, order: &[usize]) -> bool {
+    let gap = worst_gap(order);
+    if order.len() != CLIENT_COUNT * ROUNDS || gap >= CLIENT_COUNT {
+        println!(
+            "ipc stress: {} FAILED: served {} of {}, worst gap {}",
+            name,
+            order.len(),
+            CLIENT_COUNT * ROUNDS,
+            gap
+        );
+        false
+    } else {
+        println!("ipc stress: {} ok, worst gap {}", name, gap);
+        true
+    }
+}
+
+fn request_fairness() -> bool {
+    let (server, client) = syscalls::create_session().unwrap();
+
+    let clients: Vec<_> = (0..CLIENT_COUNT)
+        .map(|id| {
+            thread::spawn(move || {
+                let mut buffer = [0u8; IPC_BUFFER_SIZE];
+                for _ in 0..ROUNDS {
+                    {
+                        let mut msg = IPCMessage::new(&mut buffer);
+                        msg.write(id);
+                        msg.write_header_for(1);
+                    }
+                    syscalls::ipc_request(client, &mut buffer).unwrap();
+                }
+            })
+        })
+        .collect();
+
+    let mut buffer = [0u8; IPC_BUFFER_SIZE];
+    let mut order = Vec::new();
+    while order.len() < CLIENT_COUNT * ROUNDS {
+        match syscalls::ipc_receive(&[server], &mut buffer) {
+            Ok(Received::Message(_)) => {}
+            other => {
+                // The clients are left blocked, they go away when we exit.
+                println!("ipc stress: receive failed: {:?}", other);
+                return false;
+            }
+        }
+
+        let mut msg = IPCMessage::new(&mut buffer);
+        msg.read_header().unwrap();
+        order.push(msg.read::<usize>().unwrap());
+
+        write_reply(&mut buffer, ()).unwrap();
+        syscalls::ipc_reply(server, &mut buffer).unwrap();
+    }
+
+    for client_thread in clients {
+        client_thread.join().unwrap();
+    }
+    syscalls::close_handle(client).unwrap();
+    syscalls::close_handle(server).unwrap();
+
+    check("requests", &order)
+}
+
+// The clients tell the server who they are once they're connected, so the order is the one the server accepted them in.
+fn connection_fairness() -> bool {
+    let port = syscalls::create_port("").unwrap();
+
+    let clients: Vec<_> = (0..CLIENT_COUNT)
+        .map(|id| {
+            thread::spawn(move || {
+                let mut buffer = [0u8; IPC_BUFFER_SIZE];
+                for _ in 0..ROUNDS {
+                    let session = syscalls::connect_to_port_handle(port).unwrap();
+                    {
+                        let mut msg = IPCMessage::new(&mut buffer);
+                        msg.write(id);
+                        msg.write_header_for(1);
+                    }
+                    syscalls::ipc_notify(session, &mut buffer).unwrap();
+                    syscalls::close_handle(session).unwrap();
+                }
+            })
+        })
+        .collect();
+
+    let mut buffer = [0u8; IPC_BUFFER_SIZE];
+    let mut order = Vec::new();
+    for badge in 0..CLIENT_COUNT * ROUNDS {
+        match syscalls::ipc_receive(&[port], &mut buffer) {
+            Ok(Received::Message(_)) => {}
+            other => {
+                println!("ipc stress: waiting on port failed: {:?}", other);
+                return false;
+            }
+        }
+
+        let session = syscalls::ipc_accept(port, badge as u64).unwrap();
+        match syscalls::ipc_receive(&[session], &mut buffer) {
+            Ok(Received::Message(_)) => {}
+            other => {
+                println!("ipc stress: receiving client id failed: {:?}", other);
+                return false;
+            }
+        }
+
+        let mut msg = IPCMessage::new(&mut buffer);
+        msg.read_header().unwrap();
+        order.push(msg.read::<usize>().unwrap());
+        syscalls::close_handle(session).unwrap();
+    }
+
+    for client_thread in clients {
+        client_thread.join().unwrap();
+    }
+    syscalls::close_handle(port).unwrap();
+
+    check("connections", &order)
+}
+
+pub fn run() -> bool {
+    let requests_ok = request_fairness();
+    let connections_ok = connection_fairness();
+    requests_ok && connections_ok
+}
@@ -1,3 +1,5 @@
+mod ipc_stress;
+
 use process::ipc;
 use process::syscalls;
 
@@ -8,11 +10,17 @@ fn main() {
 
     if let Ok(file_handle) = ipc::fs::open_file("efi/boot/bootx64.efi".to_string()) {
         println!("Hello again from test: {:?}", file_handle);
-        println!("Reading file: {:?}", ipc::fs::read_file(file_handle.0, 0));
+        println!("Reading file: {:?}", ipc::fs::read_file(file_handle.0, 0, 16));
     } else {
         println!("Probably failed to open file..");
     }
 
+    if ipc_stress::run() {
+        println!("IPC stress test passed");
+    } else {
+        println!("IPC stress test failed");
+    }
+
     println!("Sleeping for 1 second...");
     syscalls::sleep_ns(1 * SECOND);
     println!("*yawn*");
@@ -0,0 +1,476 @@
+use quote::{format_ident, quote};
+use serde_derive::Deserialize;
+use std::env;
+use std::fs;
+use std::path::Path;
+use syn::__private::TokenStream2;
+use syn::Type;
+
+// x86_64 loses rcx and r11 to syscall, and the kernel's entry uses r9.
+const MAX_INPUTS: usize = 5;
+
+const X86_64_ARG_REGS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+
+#[derive(Debug, Deserialize)]
+struct Input {
+    name: String,
+    ty: String,
+    user_ty: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Out {
+    name: String,
+    ty: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SyscallInfo {
+    name: String,
+    id: usize,
+    #[serde(default)]
+    inputs: Vec<Input>,
+    output: Option<String>,
+    out: Option<Out>,
+    arch_specific: Option<bool>,
+    emulated: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SyscallConfig {
+    syscalls: Vec<SyscallInfo>,
+}
+
+enum Output {
+    Nothing,
+    Never,
+    Result,
+    Value(String),
+}
+
+impl SyscallInfo {
+    fn output(&self) -> Output {
+        match self.output.as_deref() {
+            None => Output::Nothing,
+            Some("!") => Output::Never,
+            Some("ResultCode") => Output::Result,
+            Some(x) => Output::Value(x.to_string()),
+        }
+    }
+
+    fn wrapper_name(&self) -> syn::Ident {
+        format_ident!("syscall_wrapper_{}", self.name)
+    }
+
+    fn svc_name(&self) -> syn::Ident {
+        format_ident!("svc_{}", self.name)
+    }
+
+    fn input_names(&self) -> Vec<syn::Ident> {
+        self.inputs
+            .iter()
+            .map(|x| format_ident!("{}", x.name))
+            .collect()
+    }
+
+    // Turns each register into what the svc_ function wants.
+    fn svc_args(&self, registers: &[TokenStream2]) -> Vec<TokenStream2> {
+        self.inputs
+            .iter()
+            .zip(registers)
+            .map(|(input, reg)| from_register(&input.ty, reg))
+            .collect()
+    }
+
+    fn x86_64_wrapper(&self) -> TokenStream2 {
+        let wrapper_name = self.wrapper_name();
+        let svc_name = self.svc_name();
+        let input_names = self.input_names();
+        let registers: Vec<_> = input_names.iter().map(|x| quote!(#x)).collect();
+        let args = self.svc_args(&registers);
+
+        // Results come back in rax, and a second value in rdx.
+        let (output_type, body) = match self.output() {
+            Output::Nothing | Output::Never => {
+                (quote!(), quote!(crate::svc::#svc_name(#(#args),*);))
+            }
+            Output::Result if self.out.is_some() => (
+                quote!(-> Pair),
+                quote! {
+                    let (res, out) = crate::svc::#svc_name(#(#args),*);
+                    Pair {
+                        a: res.0 as usize,
+                        b: out as usize,
+                    }
+                },
+            ),
+            Output::Result => (quote!(-> u32), quote!(crate::svc::#svc_name(#(#args),*).0)),
+            Output::Value(_) => (
+                quote!(-> usize),
+                quote!(crate::svc::#svc_name(#(#args),*) as usize),
+            ),
+        };
+
+        quote! {
+            #[no_mangle]
+            unsafe extern "C" fn #wrapper_name(#(#input_names: usize),*) #output_type {
+                #body
+            }
+        }
+    }
+
+    fn aarch64_wrapper(&self) -> TokenStream2 {
+        let wrapper_name = self.wrapper_name();
+        let svc_name = self.svc_name();
+        let registers: Vec<_> = (0..self.inputs.len())
+            .map(|i| {
+                let i = syn::Index::from(i);
+                quote!(ctx.regs[#i])
+            })
+            .collect();
+        let args = self.svc_args(&registers);
+
+        // Results go back in x0, and a second value in x1.
+        let body = match self.output() {
+            Output::Nothing | Output::Never => quote!(crate::svc::#svc_name(#(#args),*);),
+            Output::Result if self.out.is_some() => quote! {
+                let (res, out) = crate::svc::#svc_name(#(#args),*);
+                ctx.regs[0] = res.0 as usize;
+                ctx.regs[1] = out as usize;
+            },
+            Output::Result => quote! {
+                let res = crate::svc::#svc_name(#(#args),*);
+                ctx.regs[0] = res.0 as usize;
+            },
+            Output::Value(_) => quote! {
+                let res = crate::svc::#svc_name(#(#args),*);
+                ctx.regs[0] = res as usize;
+            },
+        };
+
+        let uses_ctx =
+            !self.inputs.is_empty() || !matches!(self.output(), Output::Nothing | Output::Never);
+        let ctx_name = if uses_ctx {
+            format_ident!("ctx")
+        } else {
+            format_ident!("_ctx")
+        };
+
+        quote! {
+            fn #wrapper_name(#ctx_name: &mut crate::arch::context::ExceptionContext) {
+                #body
+            }
+        }
+    }
+
+    fn user_params(&self) -> Vec<TokenStream2> {
+        let mut params: Vec<_> = self
+            .inputs
+            .iter()
+            .map(|x| {
+                let name = format_ident!("{}", x.name);
+                let ty: Type = syn::parse_str(x.user_ty.as_ref().unwrap_or(&x.ty)).unwrap();
+                quote!(#name: #ty)
+            })
+            .collect();
+
+        if let Some(out) = &self.out {
+            let name = format_ident!("{}", out.name);
+            let ty: Type = syn::parse_str(&out.ty).unwrap();
+            params.push(quote!(#name: *mut #ty));
+        }
+        params
+    }
+
+    fn user_output_type(&self) -> TokenStream2 {
+        match self.output() {
+            Output::Nothing => quote!(),
+            Output::Never => quote!(-> !),
+            Output::Result => quote!(-> ResultCode),
+            Output::Value(x) => {
+                let ty: Type = syn::parse_str(&x).unwrap();
+                quote!(-> #ty)
+            }
+        }
+    }
+
+    fn user_declaration(&self) -> TokenStream2 {
+        let fn_name = format_ident!("syscall_{}", self.name);
+        let params = self.user_params();
+        let output_type = self.user_output_type();
+
+        quote!(pub fn #fn_name(#(#params),*) #output_type;)
+    }
+
+    // Off francium there's no kernel to ask, so anything not marked emulated fails with NotImplemented.
+    // Syscalls that can't fail just return 0, and the ones that never return end the process.
+    fn emulated_stub(&self) -> TokenStream2 {
+        let fn_name = format_ident!("syscall_{}", self.name);
+        let params = self.user_params();
+        let output_type = self.user_output_type();
+
+        let body = match self.output() {
+            Output::Nothing => quote!(),
+            Output::Never => quote!(std::process::exit(0)),
+            Output::Result => quote!(ResultCode::new(Module::LibProcess, Reason::NotImplemented)),
+            Output::Value(_) => quote!(0),
+        };
+
+        quote! {
+            #[allow(unused_variables)]
+            pub unsafe fn #fn_name(#(#params),*) #output_type {
+                #body
+            }
+        }
+    }
+
+    // The kernel's result is in rax already, the stub just has to store the second value.
+    fn x86_64_stub(&self) -> String {
+        let mut stub = format!("syscall_{}:\n", self.name);
+        if self.out.is_some() {
+            stub += "push rbx\n";
+        }
+        stub += &format!("mov eax, 0x{:02x}\n", self.id);
+        if self.out.is_some() {
+            stub += &format!("mov rbx, {}\n", X86_64_ARG_REGS[self.inputs.len()]);
+        }
+
+        if self.inputs.len() >= 4 {
+            stub += "mov r10, rcx\n";
+        }
+        stub += "syscall\n";
+
+        if let Some(out) = &self.out {
+            if is_32_bit(&out.ty) {
+                stub += "mov [rbx], edx\n";
+            } else {
+                stub += "mov [rbx], rdx\n";
+            }
+            stub += "pop rbx\n";
+        }
+        stub += "ret\n";
+        stub
+    }
+
+    fn aarch64_stub(&self) -> String {
+        let mut stub = format!("syscall_{}:\n", self.name);
+        if self.out.is_some() {
+            stub += &format!("mov x9, x{}\n", self.inputs.len());
+        }
+        stub += &format!("svc #0x{:02x}\n", self.id);
+
+        if let Some(out) = &self.out {
+            if is_32_bit(&out.ty) {
+                stub += "str w1, [x9]\n";
+            } else {
+                stub += "str x1, [x9]\n";
+            }
+        }
+        stub += "ret\n";
+        stub
+    }
+}
+
+fn from_register(ty: &str, reg: &TokenStream2) -> TokenStream2 {
+    match ty {
+        "usize" => quote!(#reg),
+        "u64" | "u32" | "i32" => {
+            let ty: Type = syn::parse_str(ty).unwrap();
+            quote!(#reg as #ty)
+        }
+        "PhysAddr" => quote!(francium_common::types::PhysAddr(#reg)),
+        _ if ty.starts_with("UserPtr<") => quote!(crate::user_ptr::UserPtr::new(#reg)),
+        _ => panic!("Can't pass a {} in a register!", ty),
+    }
+}
+
+fn is_32_bit(ty: &str) -> bool {
+    match ty {
+        "Handle" | "u32" => true,
+        "usize" | "u64" => false,
+        _ => panic!("Can't return a {} in a register!", ty),
+    }
+}
+
+fn load(path: &str) -> Vec<SyscallInfo> {
+    let mut spec = toml::from_str::<SyscallConfig>(&fs::read_to_string(path).unwrap()).unwrap();
+    spec.syscalls.sort_by_key(|x| x.id);
+
+    for pair in spec.syscalls.windows(2) {
+        if pair[0].id == pair[1].id {
+            panic!(
+                "Syscalls {} and {} both have ID {:#x}!",
+                pair[0].name, pair[1].name, pair[0].id
+            );
+        }
+    }
+
+    for syscall in &spec.syscalls {
+        if syscall.inputs.len() > MAX_INPUTS {
+            panic!("Syscall {} has too many inputs!", syscall.name);
+        }
+
+        if syscall.out.is_some() && !matches!(syscall.output(), Output::Result) {
+            panic!(
+                "Syscall {} has an out value, but doesn't return a ResultCode!",
+                syscall.name
+            );
+        }
+
+        for input in &syscall.inputs {
+            let user_ty = input.user_ty.as_ref().unwrap_or(&input.ty);
+            if user_ty.starts_with("UserPtr<") || user_ty == "PhysAddr" {
+                panic!(
+                    "Syscall {} input {} needs a user_ty!",
+                    syscall.name, input.name
+                );
+            }
+        }
+    }
+
+    spec.syscalls
+}
+
+// Anything missing from the table returns NotImplemented.
+fn table_entries(syscalls: &[SyscallInfo]) -> Vec<syn::Ident> {
+    let count = syscalls.last().map_or(0, |x| x.id + 1);
+    let mut entries = vec![format_ident!("syscall_wrapper_unused"); count];
+    for syscall in syscalls {
+        entries[syscall.id] = syscall.wrapper_name();
+    }
+    entries
+}
+
+fn generate_x86_64_kernel(syscalls: &[SyscallInfo]) -> String {
+    let wrappers: Vec<_> = syscalls
+        .iter()
+        .filter(|x| !x.arch_specific.unwrap_or(false))
+        .map(|x| x.x86_64_wrapper())
+        .collect();
+    let entries = table_entries(syscalls);
+    let count = entries.len();
+
+    let kernel_impl = quote! {
+        #(#wrappers)*
+
+        pub const SYSCALL_COUNT: usize = #count;
+
+        pub static mut SYSCALL_WRAPPERS: [*const usize; SYSCALL_COUNT] = [
+            #(#entries as *const usize),*
+        ];
+    };
+    kernel_impl.to_string()
+}
+
+fn generate_aarch64_kernel(syscalls: &[SyscallInfo]) -> String {
+    let wrappers: Vec<_> = syscalls
+        .iter()
+        .filter(|x| !x.arch_specific.unwrap_or(false))
+        .map(|x| x.aarch64_wrapper())
+        .collect();
+    let entries = table_entries(syscalls);
+    let count = entries.len();
+
+    let kernel_impl = quote! {
+        #(#wrappers)*
+
+        type SVCHandler = fn(&mut crate::arch::context::ExceptionContext);
+        pub const SVC_HANDLERS: [SVCHandler; #count] = [
+            #(#entries),*
+        ];
+    };
+    kernel_impl.to_string()
+}
+
+fn generate_asm(
+    syscalls: &[SyscallInfo],
+    header: &str,
+    stub: fn(&SyscallInfo) -> String,
+) -> String {
+    let mut asm = String::new();
+    for syscall in syscalls {
+        asm += &format!(".global syscall_{}\n", syscall.name);
+    }
+    asm += "\n.section .text\n";
+    asm += header;
+
+    for syscall in syscalls {
+        asm += "\n";
+        asm += &stub(syscall);
+    }
+    asm
+}
+
+const X86_64_ASM_HEADER: &str = "
+// sysv abi register order: rdi, rsi, rdx, rcx, r8, r9
+// sysv abi callee save: rbx, rsp, rbp, r12, r13, r14, and r15;
+// rcx is clobbered by syscall, so move it into r10
+
+// The sysv abi returns 128 bit values in rax:rdx.
+";
+
+// Writes the register wrappers and dispatch table for each architecture, for the kernel to include.
+pub fn generate_kernel(path: &str) {
+    let syscalls = load(path);
+
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("x86_64_svc_wrappers.rs"),
+        generate_x86_64_kernel(&syscalls),
+    )
+    .unwrap();
+    fs::write(
+        Path::new(&out_dir).join("aarch64_svc_wrappers.rs"),
+        generate_aarch64_kernel(&syscalls),
+    )
+    .unwrap();
+
+    println!("cargo:rerun-if-changed={}", path);
+}
+
+// Writes the extern declarations and the assembly stubs behind them, for libprocess to include.
+// Also writes stand-ins for the same functions, for building libprocess anywhere other than francium.
+pub fn generate_userspace(path: &str) {
+    let syscalls = load(path);
+
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+
+    let declarations: Vec<_> = syscalls.iter().map(|x| x.user_declaration()).collect();
+    let user_impl = quote! {
+        extern "C" {
+            #(#declarations)*
+        }
+    };
+    fs::write(
+        Path::new(&out_dir).join("syscalls_impl.rs"),
+        user_impl.to_string(),
+    )
+    .unwrap();
+
+    let stubs: Vec<_> = syscalls
+        .iter()
+        .filter(|x| !x.emulated.unwrap_or(false))
+        .map(|x| x.emulated_stub())
+        .collect();
+    let emulated_impl = quote! {
+        #(#stubs)*
+    };
+    fs::write(
+        Path::new(&out_dir).join("syscalls_emulated_impl.rs"),
+        emulated_impl.to_string(),
+    )
+    .unwrap();
+
+    fs::write(
+        Path::new(&out_dir).join("x86_64_syscalls.s"),
+        generate_asm(&syscalls, X86_64_ASM_HEADER, SyscallInfo::x86_64_stub),
+    )
+    .unwrap();
+    fs::write(
+        Path::new(&out_dir).join("aarch64_syscalls.s"),
+        generate_asm(&syscalls, "", SyscallInfo::aarch64_stub),
+    )
+    .unwrap();
+
+    println!("cargo:rerun-if-changed={}", path);
+}